@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use serde::Deserialize;
+use testit_lib::{error::ApplicationError, har::Har, verify::{verify, VerificationRequest}};
+
+use crate::journal::RequestJournal;
+
+/**
+ * The filters of the journal request.
+ */
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JournalQuery {
+    // Only return requests received by the server with this ID.
+    server_id: Option<String>,
+    // Only return requests matched by the endpoint with this ID. Use "unmatched" for requests without a match.
+    endpoint_id: Option<String>,
+}
+
+/**
+ * Start the admin server used to inspect the daemon.
+ *
+ * # Arguments
+ * @param port: The port to listen on.
+ * @param journal: The request journal shared by all servers.
+ *
+ * # Returns
+ * @return Ok if the admin server was started.
+ *
+ * # Errors
+ * @return An error if the admin server could not be started.
+ */
+pub async fn start_admin_server(port: u16, journal: Arc<RequestJournal>) -> Result<(), ApplicationError> {
+    let journal = web::Data::from(journal);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(journal.clone())
+            .route("/__admin/requests", web::get().to(get_requests))
+            .route("/__admin/requests", web::delete().to(delete_requests))
+            .route("/__admin/verify", web::post().to(verify_requests))
+            .route("/__admin/har", web::get().to(get_har))
+    }).bind(("127.0.0.1", port)).map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
+    let server = server.workers(1).run();
+    tokio::spawn(async move {
+        match server.await {
+            Ok(_) => {},
+            Err(err) => eprintln!("{}", err),
+        }
+    });
+    Ok(())
+}
+
+/**
+ * Get the journal entries matching the filters.
+ *
+ * # Arguments
+ * @param journal: The request journal.
+ * @param query: The filters.
+ *
+ * # Returns
+ * @return The matching entries as JSON with the oldest first.
+ */
+async fn get_requests(journal: web::Data<RequestJournal>, query: web::Query<JournalQuery>) -> HttpResponse {
+    let entries: Vec<_> = journal.entries()
+        .into_iter()
+        .filter(|entry| query.server_id.as_ref().is_none_or(|server_id| &entry.server_id == server_id))
+        .filter(|entry| query.endpoint_id.as_ref().is_none_or(|endpoint_id| &entry.endpoint_id == endpoint_id))
+        .collect();
+    HttpResponse::Ok().json(entries)
+}
+
+/**
+ * Remove all journal entries.
+ *
+ * # Arguments
+ * @param journal: The request journal.
+ *
+ * # Returns
+ * @return An empty response.
+ */
+async fn delete_requests(journal: web::Data<RequestJournal>) -> HttpResponse {
+    journal.clear();
+    HttpResponse::NoContent().finish()
+}
+
+/**
+ * Verify the calls recorded in the journal.
+ *
+ * # Arguments
+ * @param journal: The request journal.
+ * @param verification: The expected calls.
+ *
+ * # Returns
+ * @return The verification result as JSON, or bad request if a pattern is invalid.
+ */
+async fn verify_requests(journal: web::Data<RequestJournal>, verification: web::Json<VerificationRequest>) -> HttpResponse {
+    match verify(&journal.entries(), &verification) {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(err) => HttpResponse::BadRequest().body(err.to_string()),
+    }
+}
+
+/**
+ * Export the journal entries as a HAR document.
+ *
+ * # Arguments
+ * @param journal: The request journal.
+ *
+ * # Returns
+ * @return The HAR document as JSON.
+ */
+async fn get_har(journal: web::Data<RequestJournal>) -> HttpResponse {
+    HttpResponse::Ok().json(Har::new(&journal.entries()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use actix_web::test::{self, TestRequest};
+    use testit_lib::verify::{verify_remote, VerificationResult};
+
+    use crate::journal::create_entry;
+
+    use super::*;
+
+    /**
+     * Verifying the verification of the journal entries.
+     */
+    #[actix_web::test]
+    async fn test_verify_requests() {
+        let journal = Arc::new(RequestJournal::new(10));
+        for body in ["{\"id\": 1}", "{\"id\": 2}"] {
+            let request = TestRequest::post().uri("/orders").to_http_request();
+            journal.record(create_entry("2024-01-01T00:00:00+00:00".to_string(), "server", Some("orders"), &request, body.as_bytes(), HttpResponse::Ok().finish(), Duration::ZERO).0);
+        }
+        let app = test::init_service(App::new().app_data(web::Data::from(journal)).route("/__admin/verify", web::post().to(verify_requests))).await;
+        let request = TestRequest::post().uri("/__admin/verify").set_json(VerificationRequest::new("orders".to_string()).with_body("\"id\": 1".to_string()).with_count(2)).to_request();
+        let result: VerificationResult = test::call_and_read_body_json(&app, request).await;
+        assert!(!result.passed);
+        assert_eq!(result.expected_count, Some(2));
+        assert_eq!(result.actual_count, 1);
+        assert_eq!(result.mismatches[0].body, "{\"id\": 2}");
+        let request = TestRequest::post().uri("/__admin/verify").set_json(VerificationRequest::new("orders".to_string()).with_body("(".to_string())).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 400);
+    }
+
+    /**
+     * Verifying the calls received by a started admin server with the library client.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_verify_remote() {
+        let journal = Arc::new(RequestJournal::new(10));
+        let request = TestRequest::post().uri("/orders").to_http_request();
+        journal.record(create_entry("2024-01-01T00:00:00+00:00".to_string(), "server", Some("orders"), &request, b"{\"id\": 1}", HttpResponse::Ok().finish(), Duration::ZERO).0);
+        start_admin_server(8108, journal).await.unwrap();
+        let result = verify_remote("http://localhost:8108/", &VerificationRequest::new("orders".to_string()).with_count(1)).await.unwrap();
+        assert!(result.passed);
+        assert_eq!(result.actual_count, 1);
+        assert!(!verify_remote("http://localhost:8108", &VerificationRequest::new("missing".to_string())).await.unwrap().passed);
+        assert!(verify_remote("http://localhost:8108", &VerificationRequest::new("orders".to_string()).with_body("(".to_string())).await.is_err());
+    }
+}
@@ -15,4 +15,16 @@ pub struct Args {
     /// Lists the available tests in the specified file.
     #[arg(long)]
     pub list: bool,
+
+    /// Starts the admin server on the specified port. The admin server is used to inspect the received requests.
+    #[arg(long)]
+    pub admin_port: Option<u16>,
+
+    /// Writes the received requests to the specified HAR file when the daemon is stopped.
+    #[arg(long)]
+    pub har_file: Option<String>,
+
+    /// Records the responses of routed endpoints and adds them as a new test to the specified file when the daemon is stopped.
+    #[arg(long)]
+    pub record: Option<String>,
 }
\ No newline at end of file
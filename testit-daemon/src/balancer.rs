@@ -0,0 +1,334 @@
+use std::{pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}, time::{Duration, Instant}};
+
+use actix_web::{body::{BodySize, MessageBody}, web::Bytes, HttpResponse};
+use rand::{rngs::StdRng, Rng};
+use testit_lib::{config::{EjectionConfiguration, EndpointConfiguration, LoadBalancingStrategy, RouteConfiguration, UpstreamTargetConfiguration}, error::ApplicationError};
+
+use crate::{latency::{create_generators, BALANCER_SEED_SALT}, sequence::select_weighted};
+
+/**
+ * The UpstreamBalancer struct selects the upstream target of each request to a routed endpoint and ejects
+ * targets that keep failing.
+ */
+pub struct UpstreamBalancer {
+    // The target pool by endpoint index. None for endpoints without a route.
+    pools: Vec<Option<Arc<TargetPool>>>,
+}
+
+/**
+ * The upstream targets of a route.
+ */
+struct TargetPool {
+    // The upstream targets.
+    targets: Vec<UpstreamTargetConfiguration>,
+    // How the target of each request is selected.
+    strategy: LoadBalancingStrategy,
+    // When targets that keep failing are ejected. Targets are never ejected if not set.
+    ejection: Option<EjectionConfiguration>,
+    // The state of the targets.
+    state: Mutex<PoolState>,
+}
+
+/**
+ * The selection state of a target pool.
+ */
+struct PoolState {
+    // The index of the target the round robin strategy tries next.
+    next: usize,
+    // The random generator of the random and weighted strategies.
+    generator: StdRng,
+    // The state by target index.
+    targets: Vec<TargetState>,
+}
+
+/**
+ * The state of an upstream target.
+ */
+#[derive(Default)]
+struct TargetState {
+    // The number of open connections.
+    active: usize,
+    // The number of failed requests in a row.
+    failures: u32,
+    // The time the ejection ends, or None if the target is not ejected.
+    ejected_until: Option<Instant>,
+}
+
+/**
+ * A selected upstream target. The connection to the target counts as open until the lease is dropped.
+ */
+pub struct Lease {
+    // The pool of the target.
+    pool: Arc<TargetPool>,
+    // The index of the target.
+    index: usize,
+}
+
+/**
+ * A response body that holds the lease of the upstream target until the body is sent.
+ */
+struct LeasedBody<B> {
+    // The response body.
+    body: B,
+    // The lease of the upstream target that sends the body.
+    lease: Lease,
+}
+
+impl UpstreamBalancer {
+    /**
+     * Create a new upstream balancer for the endpoints.
+     *
+     * # Arguments
+     * @param endpoints: The endpoint configurations.
+     * @param seed: The seed of the random generators. If None the generators are seeded from the operating system.
+     *
+     * # Returns
+     * @return The upstream balancer.
+     *
+     * # Errors
+     * @return An error if a route is invalid.
+     */
+    pub fn new(endpoints: &[EndpointConfiguration], seed: Option<u64>) -> Result<Self, ApplicationError> {
+        for endpoint in endpoints.iter() {
+            if let Some(route) = &endpoint.route {
+                validate(&endpoint.id, route)?;
+            }
+        }
+        let pools = endpoints
+            .iter()
+            .zip(create_generators(endpoints.len(), seed, BALANCER_SEED_SALT))
+            .map(|(endpoint, generator)| endpoint.route.as_ref().map(|route| Arc::new(TargetPool::new(route, generator.into_inner().unwrap_or_else(|err| err.into_inner())))))
+            .collect();
+        Ok(UpstreamBalancer { pools })
+    }
+
+    /**
+     * Select the upstream target of a request to the endpoint. Ejected targets are skipped unless all targets are ejected.
+     *
+     * # Arguments
+     * @param index: The index of the endpoint.
+     *
+     * # Returns
+     * @return The lease of the selected target, or None if the endpoint has no route.
+     */
+    pub fn select(&self, index: usize) -> Option<Lease> {
+        let pool = self.pools[index].as_ref()?;
+        let mut state = pool.state.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        for target in state.targets.iter_mut() {
+            if target.ejected_until.is_some_and(|ejected_until| ejected_until <= now) {
+                target.ejected_until = None;
+            }
+        }
+        let mut candidates: Vec<usize> = (0..state.targets.len()).filter(|&target| state.targets[target].ejected_until.is_none()).collect();
+        if candidates.is_empty() {
+            candidates = (0..state.targets.len()).collect();
+        }
+        let selected = match pool.strategy {
+            LoadBalancingStrategy::RoundRobin => {
+                let count = state.targets.len();
+                let selected = (0..count).map(|offset| (state.next + offset) % count).find(|target| candidates.contains(target)).unwrap_or(candidates[0]);
+                state.next = (selected + 1) % count;
+                selected
+            }
+            LoadBalancingStrategy::Random => candidates[state.generator.gen_range(0..candidates.len())],
+            LoadBalancingStrategy::LeastConnections => candidates.iter().copied().min_by_key(|&target| state.targets[target].active).unwrap_or(candidates[0]),
+            LoadBalancingStrategy::Weighted => {
+                let weights: Vec<u32> = candidates.iter().map(|&target| pool.targets[target].weight).collect();
+                match select_weighted(&weights, &mut state.generator) {
+                    Some(selected) => candidates[selected],
+                    // Only targets with weight 0 are left, so the ejected targets are used as well.
+                    None => {
+                        let weights: Vec<u32> = pool.targets.iter().map(|target| target.weight).collect();
+                        select_weighted(&weights, &mut state.generator).unwrap_or(candidates[0])
+                    }
+                }
+            }
+        };
+        state.targets[selected].active += 1;
+        Some(Lease { pool: pool.clone(), index: selected })
+    }
+}
+
+impl TargetPool {
+    /**
+     * Create a new target pool for the route. A route without targets has its endpoint as the only target.
+     *
+     * # Arguments
+     * @param route: The route configuration.
+     * @param generator: The random generator of the pool.
+     *
+     * # Returns
+     * @return The target pool.
+     */
+    fn new(route: &RouteConfiguration, generator: StdRng) -> Self {
+        let targets = match route.targets.is_empty() {
+            true => vec![UpstreamTargetConfiguration::new(route.endpoint.clone(), 1)],
+            false => route.targets.clone(),
+        };
+        let state = PoolState {
+            next: 0,
+            generator,
+            targets: targets.iter().map(|_| TargetState::default()).collect(),
+        };
+        TargetPool {
+            targets,
+            strategy: route.strategy,
+            ejection: route.ejection.clone(),
+            state: Mutex::new(state),
+        }
+    }
+}
+
+impl Lease {
+    /**
+     * Get the URL of the selected target.
+     *
+     * # Returns
+     * @return The URL of the target.
+     */
+    pub fn endpoint(&self) -> &str {
+        &self.pool.targets[self.index].endpoint
+    }
+
+    /**
+     * Report the outcome of the request. The target is ejected when the number of failures in a row reaches the
+     * ejection threshold.
+     *
+     * # Arguments
+     * @param success: True if the target responded without a server error.
+     */
+    pub fn report(&self, success: bool) {
+        let mut state = self.pool.state.lock().unwrap_or_else(|err| err.into_inner());
+        let target = &mut state.targets[self.index];
+        if success {
+            target.failures = 0;
+            return;
+        }
+        target.failures += 1;
+        if let Some(ejection) = &self.pool.ejection {
+            if target.failures >= ejection.consecutive_failures {
+                target.failures = 0;
+                target.ejected_until = Some(Instant::now() + Duration::from_millis(ejection.duration));
+            }
+        }
+    }
+
+    /**
+     * Hold the lease until the response body is sent, so the connection counts as open while the body is streamed.
+     *
+     * # Arguments
+     * @param response: The upstream response.
+     *
+     * # Returns
+     * @return The response with the lease attached to the body.
+     */
+    pub fn attach(self, response: HttpResponse) -> HttpResponse {
+        response.map_body(|_, body| LeasedBody { body, lease: self }).map_into_boxed_body()
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut state = self.pool.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.targets[self.index].active -= 1;
+    }
+}
+
+impl<B: MessageBody + Unpin> MessageBody for LeasedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.get_mut().body).poll_next(cx)
+    }
+
+    fn try_into_bytes(self) -> Result<Bytes, Self> {
+        let LeasedBody { body, lease } = self;
+        body.try_into_bytes().map_err(|body| LeasedBody { body, lease })
+    }
+}
+
+/**
+ * Validate the route.
+ *
+ * # Arguments
+ * @param endpoint_id: The ID of the endpoint with the route.
+ * @param route: The route configuration.
+ *
+ * # Returns
+ * @return Ok if the route is valid.
+ *
+ * # Errors
+ * @return An error if the route has neither an endpoint nor targets, or both.
+ * @return An error if all weights are zero for the weighted strategy.
+ * @return An error if the ejection threshold is zero.
+ */
+fn validate(endpoint_id: &str, route: &RouteConfiguration) -> Result<(), ApplicationError> {
+    if route.endpoint.is_empty() == route.targets.is_empty() {
+        return Err(ApplicationError::ConfigurationError(format!("The route of endpoint {} requires either an endpoint or targets", endpoint_id)));
+    }
+    if route.strategy == LoadBalancingStrategy::Weighted && route.targets.iter().all(|target| target.weight == 0) {
+        return Err(ApplicationError::ConfigurationError(format!("The route of endpoint {} has only targets with weight 0", endpoint_id)));
+    }
+    if route.ejection.as_ref().is_some_and(|ejection| ejection.consecutive_failures == 0) {
+        return Err(ApplicationError::ConfigurationError(format!("The route of endpoint {} ejects targets after 0 failures", endpoint_id)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /**
+     * Verifying the target selection of each strategy.
+     */
+    #[test]
+    fn test_select() {
+        let targets = vec![UpstreamTargetConfiguration::new("http://a".to_string(), 1), UpstreamTargetConfiguration::new("http://b".to_string(), 3)];
+        let route = |strategy| Some(RouteConfiguration::new(String::new()).with_targets(targets.clone(), strategy));
+        let endpoints = vec![
+            EndpointConfiguration::new("^/round$".to_string(), "GET".to_string(), None, None, route(LoadBalancingStrategy::RoundRobin)),
+            EndpointConfiguration::new("^/least$".to_string(), "GET".to_string(), None, None, route(LoadBalancingStrategy::LeastConnections)),
+            EndpointConfiguration::new("^/weighted$".to_string(), "GET".to_string(), None, None, route(LoadBalancingStrategy::Weighted)),
+        ];
+        let balancer = UpstreamBalancer::new(&endpoints, Some(1)).unwrap();
+        let selected: Vec<String> = (0..3).map(|_| balancer.select(0).unwrap().endpoint().to_string()).collect();
+        assert_eq!(selected, vec!["http://a", "http://b", "http://a"]);
+        let first = balancer.select(1).unwrap();
+        assert_eq!(first.endpoint(), "http://a");
+        assert_eq!(balancer.select(1).unwrap().endpoint(), "http://b");
+        let response = first.attach(HttpResponse::Ok().streaming(futures_util::stream::once(async { Ok::<_, actix_web::Error>(Bytes::from("open")) })));
+        assert_eq!(balancer.select(1).unwrap().endpoint(), "http://b");
+        drop(response);
+        assert_eq!(balancer.select(1).unwrap().endpoint(), "http://a");
+        let weighted = (0..1000).filter(|_| balancer.select(2).unwrap().endpoint() == "http://b").count();
+        assert!((650..850).contains(&weighted), "{}", weighted);
+    }
+
+    /**
+     * Verifying that a target is ejected after failing in a row and returns when the ejection ends.
+     */
+    #[test]
+    fn test_ejection() {
+        let targets = vec![UpstreamTargetConfiguration::new("http://a".to_string(), 1), UpstreamTargetConfiguration::new("http://b".to_string(), 1)];
+        let route = RouteConfiguration::new(String::new()).with_targets(targets, LoadBalancingStrategy::RoundRobin).with_ejection(EjectionConfiguration::new(2, 100));
+        let balancer = UpstreamBalancer::new(&[EndpointConfiguration::new("^/$".to_string(), "GET".to_string(), None, None, Some(route.clone()))], None).unwrap();
+        for success in [false, true, false, false] {
+            let lease = balancer.select(0).unwrap();
+            lease.report(success || lease.endpoint() == "http://b");
+        }
+        assert!((0..4).all(|_| balancer.select(0).unwrap().endpoint() == "http://b"));
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(balancer.select(0).unwrap().endpoint(), "http://a");
+
+        let invalid = route.with_ejection(EjectionConfiguration::new(0, 100));
+        assert!(UpstreamBalancer::new(&[EndpointConfiguration::new("^/$".to_string(), "GET".to_string(), None, None, Some(invalid))], None).is_err());
+        let empty = RouteConfiguration::new(String::new());
+        assert!(UpstreamBalancer::new(&[EndpointConfiguration::new("^/$".to_string(), "GET".to_string(), None, None, Some(empty))], None).is_err());
+    }
+}
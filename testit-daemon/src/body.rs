@@ -0,0 +1,162 @@
+use std::{collections::{hash_map::Entry, HashMap}, path::{Path, PathBuf}};
+
+use actix_web::web::Bytes;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use testit_lib::{config::MockResponseConfiguration, error::ApplicationError};
+
+/**
+ * The body of a mock response and the content type to use if none is configured.
+ */
+pub struct MockBody {
+    // The body.
+    pub bytes: Bytes,
+    // The content type inferred from the body source.
+    pub content_type: Option<String>,
+}
+
+/**
+ * The BodyFiles struct holds the response files and base64 bodies of a server. The files are read and the base64
+ * bodies decoded once when the server starts.
+ */
+pub struct BodyFiles {
+    // The directory relative paths are resolved from.
+    base_path: PathBuf,
+    // The file contents by resolved path.
+    files: HashMap<PathBuf, Bytes>,
+    // The decoded bodies by base64 body.
+    decoded: HashMap<String, Bytes>,
+}
+
+impl BodyFiles {
+    /**
+     * Create a new empty set of body files.
+     *
+     * # Arguments
+     * @param base_path: The directory relative paths are resolved from.
+     *
+     * # Returns
+     * @return The body files.
+     */
+    pub fn new(base_path: &Path) -> Self {
+        BodyFiles {
+            base_path: base_path.to_path_buf(),
+            files: HashMap::new(),
+            decoded: HashMap::new(),
+        }
+    }
+
+    /**
+     * Validate the body source of the mock response, read the response file and decode the base64 body.
+     *
+     * # Arguments
+     * @param mock_response: The mock response configuration.
+     *
+     * # Returns
+     * @return Ok if the body source is valid.
+     *
+     * # Errors
+     * @return An error if more than one body source is configured.
+     * @return An error if the response file could not be read.
+     * @return An error if the base64 body is invalid.
+     */
+    pub fn load(&mut self, mock_response: &MockResponseConfiguration) -> Result<(), ApplicationError> {
+        let sources = [mock_response.response.is_some(), mock_response.response_file.is_some(), mock_response.response_base64.is_some()];
+        if sources.iter().filter(|source| **source).count() > 1 {
+            return Err(ApplicationError::ConfigurationError("Only one of response, responseFile and responseBase64 can be set".to_string()));
+        }
+        if let Some(response_file) = &mock_response.response_file {
+            let path = self.resolve(response_file);
+            if let Entry::Vacant(entry) = self.files.entry(path) {
+                let bytes = std::fs::read(entry.key()).map_err(|err| ApplicationError::FileError(format!("{}: {}", entry.key().display(), err)))?;
+                entry.insert(Bytes::from(bytes));
+            }
+        }
+        if let Some(response_base64) = &mock_response.response_base64 {
+            if let Entry::Vacant(entry) = self.decoded.entry(response_base64.clone()) {
+                let bytes = STANDARD.decode(entry.key()).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?;
+                entry.insert(Bytes::from(bytes));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Get the body from the response file or the base64 body of the mock response.
+     *
+     * # Arguments
+     * @param mock_response: The mock response configuration.
+     *
+     * # Returns
+     * @return The body, or None if the mock response has no file or base64 body.
+     *
+     * # Errors
+     * @return An error if the response file or base64 body was not loaded at startup.
+     */
+    pub fn get(&self, mock_response: &MockResponseConfiguration) -> Result<Option<MockBody>, ApplicationError> {
+        if let Some(response_file) = &mock_response.response_file {
+            let path = self.resolve(response_file);
+            let bytes = self.files.get(&path).ok_or(ApplicationError::FileError(format!("{} is not loaded", path.display())))?;
+            let content_type = mime_guess::from_path(&path).first().map(|mime| mime.to_string());
+            return Ok(Some(MockBody { bytes: bytes.clone(), content_type }));
+        }
+        if let Some(response_base64) = &mock_response.response_base64 {
+            let bytes = self.decoded.get(response_base64).ok_or(ApplicationError::ConfigurationError(format!("Base64 body {} is not loaded", response_base64)))?;
+            return Ok(Some(MockBody { bytes: bytes.clone(), content_type: Some("application/octet-stream".to_string()) }));
+        }
+        Ok(None)
+    }
+
+    /**
+     * Resolve the path relative to the base path.
+     *
+     * # Arguments
+     * @param path: The path.
+     *
+     * # Returns
+     * @return The resolved path.
+     */
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.base_path.join(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /**
+     * Verifying that response files are resolved from the base path.
+     */
+    #[test]
+    fn test_response_file() {
+        let base_path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/test/resources/https_test"));
+        let mock_response = MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_response_file("server_cert.pem".to_string());
+        let mut body_files = BodyFiles::new(base_path);
+        body_files.load(&mock_response).unwrap();
+        let body = body_files.get(&mock_response).unwrap().unwrap();
+        assert_eq!(body.bytes, Bytes::from(std::fs::read(base_path.join("server_cert.pem")).unwrap()));
+        assert_eq!(body.content_type, Some("application/x-x509-ca-cert".to_string()));
+        let missing = MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_response_file("missing.json".to_string());
+        assert!(body_files.load(&missing).is_err());
+    }
+
+    /**
+     * Verifying base64 bodies.
+     */
+    #[test]
+    fn test_response_base64() {
+        let mut body_files = BodyFiles::new(Path::new(""));
+        let mock_response = MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_response_base64("AAEC/w==".to_string());
+        body_files.load(&mock_response).unwrap();
+        let body = body_files.get(&mock_response).unwrap().unwrap();
+        assert_eq!(body.bytes, Bytes::from_static(&[0, 1, 2, 255]));
+        assert_eq!(body.content_type, Some("application/octet-stream".to_string()));
+        let invalid = MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_response_base64("not base64!".to_string());
+        assert!(body_files.load(&invalid).is_err());
+        assert!(body_files.get(&invalid).is_err());
+        let both = MockResponseConfiguration::new(Some("{}".to_string()), 200, HashMap::new(), 0).with_response_base64("AAEC/w==".to_string());
+        assert!(body_files.load(&both).is_err());
+    }
+}
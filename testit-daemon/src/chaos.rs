@@ -0,0 +1,158 @@
+use std::{sync::Mutex, time::Duration};
+
+use actix_web::{http::StatusCode, HttpRequest, HttpResponse};
+use rand::{rngs::StdRng, Rng};
+use testit_lib::{config::{ChaosConfiguration, ServerConfiguration}, error::ApplicationError};
+
+use crate::{fault::execute_fault, latency::{create_generators, CHAOS_SEED_SALT}};
+
+/**
+ * The status returned by a chaos fault that sends the status and headers when no status is configured.
+ */
+const DEFAULT_CHAOS_STATUS: u16 = 500;
+
+/**
+ * The ChaosInjector struct decides which requests to a server get errors injected.
+ */
+pub struct ChaosInjector {
+    // The chaos configuration by endpoint index. The endpoint configuration replaces the server configuration.
+    chaos: Vec<Option<ChaosConfiguration>>,
+    // The random generator by endpoint index.
+    generators: Vec<Mutex<StdRng>>,
+}
+
+impl ChaosInjector {
+    /**
+     * Create a new chaos injector for the endpoints of the server.
+     *
+     * # Arguments
+     * @param server_configuration: The server configuration.
+     *
+     * # Returns
+     * @return The chaos injector.
+     *
+     * # Errors
+     * @return An error if a chaos configuration is invalid.
+     */
+    pub fn new(server_configuration: &ServerConfiguration) -> Result<Self, ApplicationError> {
+        let chaos: Vec<Option<ChaosConfiguration>> = server_configuration.endpoints.iter().map(|endpoint| endpoint.chaos.clone().or(server_configuration.chaos.clone())).collect();
+        for chaos in chaos.iter().flatten() {
+            validate(chaos)?;
+        }
+        let generators = create_generators(chaos.len(), server_configuration.seed, CHAOS_SEED_SALT);
+        Ok(ChaosInjector { chaos, generators })
+    }
+
+    /**
+     * Decide if errors are injected into the request to the endpoint.
+     *
+     * # Arguments
+     * @param index: The index of the endpoint.
+     *
+     * # Returns
+     * @return The chaos configuration if errors are injected, otherwise None.
+     */
+    pub fn select(&self, index: usize) -> Option<&ChaosConfiguration> {
+        let chaos = self.chaos[index].as_ref()?;
+        let roll = self.generators[index].lock().unwrap_or_else(|err| err.into_inner()).gen::<f64>() * 100.0;
+        (roll < chaos.percentage).then_some(chaos)
+    }
+}
+
+/**
+ * Inject the errors into the request. The delay is always applied, then the fault or the status replaces the response.
+ *
+ * # Arguments
+ * @param chaos: The chaos configuration.
+ * @param request: The request.
+ *
+ * # Returns
+ * @return The response that replaces the endpoint response, or None if the endpoint should respond after the delay.
+ *
+ * # Errors
+ * @return An error if the fault could not be executed.
+ */
+pub async fn inject_chaos(chaos: &ChaosConfiguration, request: &HttpRequest) -> Result<Option<HttpResponse>, ApplicationError> {
+    tokio::time::sleep(Duration::from_millis(chaos.delay)).await;
+    let status = StatusCode::from_u16(chaos.status.unwrap_or(DEFAULT_CHAOS_STATUS)).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?;
+    if let Some(fault) = chaos.fault {
+        return execute_fault(fault, request, HttpResponse::build(status)).await.map(Some);
+    }
+    if chaos.status.is_some() {
+        return Ok(Some(HttpResponse::build(status).finish()));
+    }
+    Ok(None)
+}
+
+/**
+ * Validate the chaos configuration.
+ *
+ * # Arguments
+ * @param chaos: The chaos configuration.
+ *
+ * # Returns
+ * @return Ok if the chaos configuration is valid.
+ *
+ * # Errors
+ * @return An error if the percentage is not between 0 and 100.
+ * @return An error if the status is invalid.
+ * @return An error if neither a status, a fault nor a delay is configured.
+ */
+fn validate(chaos: &ChaosConfiguration) -> Result<(), ApplicationError> {
+    if !(0.0..=100.0).contains(&chaos.percentage) {
+        return Err(ApplicationError::ConfigurationError(format!("Chaos percentage {} is not between 0 and 100", chaos.percentage)));
+    }
+    if let Some(status) = chaos.status {
+        StatusCode::from_u16(status).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?;
+    }
+    if chaos.status.is_none() && chaos.fault.is_none() && chaos.delay == 0 {
+        return Err(ApplicationError::ConfigurationError("Chaos requires a status, a fault or a delay".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use testit_lib::config::EndpointConfiguration;
+
+    use super::*;
+
+    /**
+     * Verifying the percentage of requests with errors and that endpoint chaos replaces server chaos.
+     */
+    #[test]
+    fn test_select() {
+        let server_configuration = ServerConfiguration::new("test".to_string(), None, vec![
+            EndpointConfiguration::new("/server".to_string(), "GET".to_string(), None, None, None),
+            EndpointConfiguration::new("/endpoint".to_string(), "GET".to_string(), None, None, None).with_chaos(ChaosConfiguration::new(100.0).with_status(500)),
+            EndpointConfiguration::new("/disabled".to_string(), "GET".to_string(), None, None, None).with_chaos(ChaosConfiguration::new(0.0).with_status(500)),
+        ], None).with_chaos(ChaosConfiguration::new(10.0).with_status(503)).with_seed(7);
+        let chaos_injector = ChaosInjector::new(&server_configuration).unwrap();
+        let selected: Vec<&ChaosConfiguration> = (0..10000).filter_map(|_| chaos_injector.select(0)).collect();
+        assert!((800..=1200).contains(&selected.len()));
+        assert!(selected.iter().all(|chaos| chaos.percentage == 10.0 && chaos.status == Some(503)));
+        let chaos = chaos_injector.select(1).unwrap();
+        assert_eq!((chaos.percentage, chaos.status), (100.0, Some(500)));
+        assert!((0..1000).all(|_| chaos_injector.select(2).is_none()));
+    }
+
+    /**
+     * Verifying that invalid chaos configurations fail.
+     */
+    #[test]
+    fn test_invalid() {
+        let valid = ChaosConfiguration::new(50.0).with_delay(100);
+        let endpoint_chaos = [
+            (ChaosConfiguration::new(150.0).with_status(503), false),
+            (ChaosConfiguration::new(10.0).with_status(1000), false),
+            (ChaosConfiguration::new(10.0), false),
+            (valid.clone(), true),
+        ];
+        for (chaos, expected) in endpoint_chaos {
+            let server_configuration = ServerConfiguration::new("test".to_string(), None, vec![
+                EndpointConfiguration::new("/endpoint".to_string(), "GET".to_string(), None, None, None).with_chaos(chaos),
+            ], None).with_chaos(valid.clone());
+            assert_eq!(ChaosInjector::new(&server_configuration).is_ok(), expected);
+        }
+    }
+}
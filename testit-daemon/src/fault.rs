@@ -0,0 +1,228 @@
+use std::{any::Any, io, net::Shutdown, time::Duration};
+
+use actix_tls::accept::openssl::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream, web::Bytes, HttpRequest, HttpResponse, HttpResponseBuilder};
+use futures_util::stream;
+use rand::RngCore;
+use socket2::SockRef;
+use testit_lib::{config::Fault, error::ApplicationError};
+
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, BorrowedSocket, RawSocket};
+
+/**
+ * The time to wait after the headers are sent before the connection is closed for the close after headers fault.
+ */
+const CLOSE_AFTER_HEADERS_DELAY: Duration = Duration::from_millis(100);
+
+/**
+ * The number of random bytes sent for the malformed response fault.
+ */
+const MALFORMED_RESPONSE_SIZE: usize = 64;
+
+/**
+ * The raw socket of a connection. It is stored in the connection data when the connection is accepted, so
+ * faults can be executed on the socket of the request.
+ */
+#[derive(Clone, Copy)]
+pub struct ConnectionSocket {
+    // The raw socket.
+    #[cfg(unix)]
+    socket: RawFd,
+    #[cfg(windows)]
+    socket: RawSocket,
+}
+
+impl ConnectionSocket {
+    /**
+     * Create the connection socket of the stream.
+     *
+     * # Arguments
+     * @param stream: The tcp stream of the connection.
+     *
+     * # Returns
+     * @return The connection socket.
+     */
+    fn of(stream: &TcpStream) -> Self {
+        #[cfg(unix)]
+        let socket = stream.as_raw_fd();
+        #[cfg(windows)]
+        let socket = stream.as_raw_socket();
+        ConnectionSocket { socket }
+    }
+
+    /**
+     * Run the function with the socket.
+     *
+     * # Arguments
+     * @param function: The function.
+     *
+     * # Returns
+     * @return The result of the function.
+     */
+    fn with_socket<R>(&self, function: impl FnOnce(SockRef) -> R) -> R {
+        // The socket is owned by the connection, which is open while its request is handled.
+        #[cfg(unix)]
+        let socket = unsafe { BorrowedFd::borrow_raw(self.socket) };
+        #[cfg(windows)]
+        let socket = unsafe { BorrowedSocket::borrow_raw(self.socket) };
+        function(SockRef::from(&socket))
+    }
+
+    /**
+     * Make the socket send a reset instead of a normal close when the connection is closed.
+     *
+     * # Returns
+     * @return Ok if the socket was changed.
+     *
+     * # Errors
+     * @return An error if the socket operation failed.
+     */
+    pub fn reset(&self) -> Result<(), ApplicationError> {
+        self.with_socket(|socket| socket.set_linger(Some(Duration::ZERO))).map_err(socket_error)
+    }
+
+    /**
+     * Close the sending side of the socket, so the client reads the end of the stream.
+     *
+     * # Returns
+     * @return Ok if the socket was closed.
+     *
+     * # Errors
+     * @return An error if the socket operation failed.
+     */
+    pub fn close_write(&self) -> Result<(), ApplicationError> {
+        self.with_socket(|socket| socket.shutdown(Shutdown::Write)).map_err(socket_error)
+    }
+
+    /**
+     * Send random data on the socket and close the sending side of the socket.
+     *
+     * # Returns
+     * @return Ok if the data was sent.
+     *
+     * # Errors
+     * @return An error if the socket operation failed.
+     */
+    pub fn send_garbage(&self) -> Result<(), ApplicationError> {
+        let mut garbage = [0u8; MALFORMED_RESPONSE_SIZE];
+        rand::thread_rng().fill_bytes(&mut garbage);
+        self.with_socket(|socket| {
+            socket.send(&garbage)?;
+            socket.shutdown(Shutdown::Write)
+        }).map_err(socket_error)
+    }
+}
+
+/**
+ * Store the socket of a new connection in the connection data. Used as the on connect callback of the servers.
+ *
+ * # Arguments
+ * @param connection: The connection stream.
+ * @param extensions: The connection data.
+ */
+pub fn on_connect(connection: &dyn Any, extensions: &mut Extensions) {
+    if let Some(stream) = connection.downcast_ref::<TcpStream>() {
+        extensions.insert(ConnectionSocket::of(stream));
+    } else if let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() {
+        extensions.insert(ConnectionSocket::of(stream.get_ref()));
+    }
+}
+
+/**
+ * Execute the fault on the connection of the request.
+ *
+ * # Arguments
+ * @param fault: The fault.
+ * @param request: The request.
+ * @param response_builder: The response builder with the configured status and headers.
+ *
+ * # Returns
+ * @return A response that makes the server close the connection. The future never completes for the hang fault.
+ *
+ * # Errors
+ * @return An error if the socket of the connection is not available.
+ * @return An error if the socket operation failed.
+ */
+pub async fn execute_fault(fault: Fault, request: &HttpRequest, mut response_builder: HttpResponseBuilder) -> Result<HttpResponse, ApplicationError> {
+    match fault {
+        Fault::Hang => std::future::pending().await,
+        Fault::CloseAfterHeaders => Ok(response_builder.streaming(failing_body(CLOSE_AFTER_HEADERS_DELAY))),
+        Fault::ConnectionReset => {
+            connection_socket(request)?.reset()?;
+            Ok(HttpResponse::Ok().streaming(failing_body(Duration::ZERO)))
+        }
+        Fault::EmptyResponse => {
+            connection_socket(request)?.close_write()?;
+            Ok(HttpResponse::Ok().streaming(failing_body(Duration::ZERO)))
+        }
+        Fault::MalformedResponse => {
+            connection_socket(request)?.send_garbage()?;
+            Ok(HttpResponse::Ok().streaming(failing_body(Duration::ZERO)))
+        }
+    }
+}
+
+/**
+ * Get the socket of the connection of the request.
+ *
+ * # Arguments
+ * @param request: The request.
+ *
+ * # Returns
+ * @return The connection socket.
+ *
+ * # Errors
+ * @return An error if the socket was not stored when the connection was accepted.
+ */
+pub fn connection_socket(request: &HttpRequest) -> Result<ConnectionSocket, ApplicationError> {
+    request.conn_data::<ConnectionSocket>().copied().ok_or(ApplicationError::FaultError("Connection socket not available".to_string()))
+}
+
+/**
+ * Convert a socket error.
+ *
+ * # Arguments
+ * @param err: The socket error.
+ *
+ * # Returns
+ * @return The application error.
+ */
+fn socket_error(err: io::Error) -> ApplicationError {
+    ApplicationError::FaultError(err.to_string())
+}
+
+/**
+ * Create a body that fails after the delay. The server closes the connection when the body fails.
+ *
+ * # Arguments
+ * @param delay: The delay before the body fails.
+ *
+ * # Returns
+ * @return The body stream.
+ */
+fn failing_body(delay: Duration) -> impl futures_util::Stream<Item = Result<Bytes, io::Error>> {
+    stream::once(async move {
+        tokio::time::sleep(delay).await;
+        Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Connection closed by fault"))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    /**
+     * Verifying that socket faults fail without a connection socket.
+     */
+    #[actix_web::test]
+    async fn test_missing_socket() {
+        let request = TestRequest::get().to_http_request();
+        assert!(execute_fault(Fault::ConnectionReset, &request, HttpResponse::Ok()).await.is_err());
+        assert!(execute_fault(Fault::CloseAfterHeaders, &request, HttpResponse::Ok()).await.is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{collections::VecDeque, sync::{atomic::{AtomicUsize, Ordering}, Mutex}, time::Duration};
+
+use actix_web::{body::{BodySize, MessageBody}, http::header::HeaderMap, web::Bytes, HttpRequest, HttpResponse};
+use testit_lib::journal::{JournalEntry, JournalResponse, UNMATCHED};
+
+/**
+ * The RequestJournal struct keeps the latest requests received by the servers of a test.
+ */
+pub struct RequestJournal {
+    // The maximum number of entries.
+    capacity: AtomicUsize,
+    // The entries with the oldest first.
+    entries: Mutex<VecDeque<JournalEntry>>,
+}
+
+impl RequestJournal {
+    /**
+     * Create a new empty request journal.
+     *
+     * # Arguments
+     * @param capacity: The maximum number of entries.
+     *
+     * # Returns
+     * @return The request journal.
+     */
+    pub fn new(capacity: usize) -> Self {
+        RequestJournal {
+            capacity: AtomicUsize::new(capacity),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /**
+     * Set the maximum number of entries. The oldest entries are removed if there are too many.
+     *
+     * # Arguments
+     * @param capacity: The maximum number of entries.
+     */
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::SeqCst);
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /**
+     * Add an entry. The oldest entry is removed if the journal is full.
+     *
+     * # Arguments
+     * @param entry: The journal entry.
+     */
+    pub fn record(&self, entry: JournalEntry) {
+        let capacity = self.capacity.load(Ordering::SeqCst);
+        if capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        while entries.len() >= capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /**
+     * Get the entries.
+     *
+     * # Returns
+     * @return The entries with the oldest first.
+     */
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner()).iter().cloned().collect()
+    }
+
+    /**
+     * Remove all entries.
+     */
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner()).clear();
+    }
+}
+
+/**
+ * Create a journal entry for the request and the response. The response body is recorded if it is not streamed.
+ *
+ * # Arguments
+ * @param timestamp: The time the request was received.
+ * @param server_id: The ID of the server.
+ * @param endpoint_id: The ID of the matched endpoint, or None if no endpoint matched.
+ * @param request: The request.
+ * @param body: The request body.
+ * @param response: The response.
+ * @param latency: The time from the request was received until the response was ready.
+ *
+ * # Returns
+ * @return The journal entry and the response with the same body.
+ */
+pub fn create_entry(timestamp: String, server_id: &str, endpoint_id: Option<&str>, request: &HttpRequest, body: &[u8], response: HttpResponse, latency: Duration) -> (JournalEntry, HttpResponse) {
+    let (response, response_body) = buffered_body(response);
+    let entry = JournalEntry {
+        timestamp,
+        server_id: server_id.to_string(),
+        endpoint_id: endpoint_id.unwrap_or(UNMATCHED).to_string(),
+        method: request.method().to_string(),
+        scheme: request.connection_info().scheme().to_string(),
+        url: request.uri().path_and_query().map(|path_and_query| path_and_query.to_string()).unwrap_or_default(),
+        headers: header_pairs(request.headers()),
+        body: String::from_utf8_lossy(body).to_string(),
+        response: JournalResponse {
+            status: response.status().as_u16(),
+            headers: header_pairs(response.headers()),
+            body: response_body.map(|body| String::from_utf8_lossy(&body).to_string()),
+            latency: latency.as_millis() as u64,
+        },
+    };
+    (entry, response)
+}
+
+/**
+ * Take the body of the response if it is already in memory, so it can be recorded.
+ *
+ * # Arguments
+ * @param response: The response.
+ *
+ * # Returns
+ * @return The response with the same body, and the body if it is not streamed.
+ */
+fn buffered_body(response: HttpResponse) -> (HttpResponse, Option<Bytes>) {
+    let (response, body) = response.into_parts();
+    match body.size() {
+        BodySize::None => (response.set_body(body), Some(Bytes::new())),
+        BodySize::Sized(_) => match body.try_into_bytes() {
+            Ok(bytes) => (response.set_body(bytes.clone()).map_into_boxed_body(), Some(bytes)),
+            Err(body) => (response.set_body(body), None),
+        },
+        BodySize::Stream => (response.set_body(body), None),
+    }
+}
+
+/**
+ * Convert the headers to name and value pairs. Values that are not valid strings are replaced.
+ *
+ * # Arguments
+ * @param headers: The headers.
+ *
+ * # Returns
+ * @return The header names and values.
+ */
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter().map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).to_string())).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    /**
+     * Verifying that the journal keeps the latest entries.
+     */
+    #[test]
+    fn test_record() {
+        let journal = RequestJournal::new(2);
+        for url in ["/1", "/2", "/3?a=b"] {
+            let request = TestRequest::post().uri(url).insert_header(("x-test", "value")).to_http_request();
+            journal.record(create_entry("2024-01-01T00:00:00+00:00".to_string(), "server", None, &request, b"body", HttpResponse::Ok().body("{}"), Duration::from_millis(5)).0);
+        }
+        let entries = journal.entries();
+        assert_eq!(entries.iter().map(|entry| entry.url.as_str()).collect::<Vec<&str>>(), vec!["/2", "/3?a=b"]);
+        assert_eq!(entries[1].endpoint_id, UNMATCHED);
+        assert_eq!(entries[1].method, "POST");
+        assert_eq!(entries[1].headers, vec![("x-test".to_string(), "value".to_string())]);
+        assert_eq!(entries[1].body, "body");
+        assert_eq!(entries[1].scheme, "http");
+        assert_eq!(entries[1].response.status, 200);
+        assert_eq!(entries[1].response.body.as_deref(), Some("{}"));
+        assert_eq!(entries[1].response.latency, 5);
+        journal.set_capacity(1);
+        assert_eq!(journal.entries().len(), 1);
+        journal.clear();
+        assert!(journal.entries().is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use std::{sync::Mutex, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, LogNormal, Normal};
+use testit_lib::{config::{DelayDistribution, EndpointConfiguration, MockResponseConfiguration}, error::ApplicationError};
+
+/**
+ * The seed salt of the delay generators.
+ */
+const DELAY_SEED_SALT: u64 = 0;
+
+/**
+ * The seed salt of the chaos generators.
+ */
+pub const CHAOS_SEED_SALT: u64 = u64::MAX;
+
+/**
+ * The seed salt of the upstream balancer generators.
+ */
+pub const BALANCER_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/**
+ * The seed salt of the WebSocket relay fault generators.
+ */
+pub const RELAY_SEED_SALT: u64 = 0xc2b2_ae3d_27d4_eb4f;
+
+/**
+ * The seed salt of the random response sequence generators.
+ */
+pub const SEQUENCE_SEED_SALT: u64 = 0x1656_67b1_9e37_79f9;
+
+/**
+ * The DelaySampler struct samples the delays of the mock responses of a server. Each endpoint has its own
+ * random generator, so the delays of an endpoint are reproducible with a seed even when other endpoints are called.
+ */
+pub struct DelaySampler {
+    // The random generator by endpoint index.
+    generators: Vec<Mutex<StdRng>>,
+    // The random generator of the fallback response.
+    fallback_generator: Mutex<StdRng>,
+}
+
+impl DelaySampler {
+    /**
+     * Create a new delay sampler for the endpoints and the fallback response.
+     *
+     * # Arguments
+     * @param endpoints: The endpoint configurations.
+     * @param fallback: The fallback response of the server.
+     * @param seed: The seed of the random generators. If None the generators are seeded from the operating system.
+     *
+     * # Returns
+     * @return The delay sampler.
+     *
+     * # Errors
+     * @return An error if a delay distribution is invalid.
+     */
+    pub fn new(endpoints: &[EndpointConfiguration], fallback: Option<&MockResponseConfiguration>, seed: Option<u64>) -> Result<Self, ApplicationError> {
+        for mock_response in endpoints.iter().flat_map(|endpoint| endpoint.mock_responses()).chain(fallback) {
+            if let Some(delay_distribution) = &mock_response.delay_distribution {
+                validate(delay_distribution)?;
+            }
+        }
+        // The fallback generator follows the endpoint generators.
+        let mut generators = create_generators(endpoints.len() + 1, seed, DELAY_SEED_SALT);
+        let fallback_generator = generators.remove(endpoints.len());
+        Ok(DelaySampler { generators, fallback_generator })
+    }
+
+    /**
+     * Get the delay of the mock response. The delay is the fixed delay plus a sample from the delay distribution.
+     *
+     * # Arguments
+     * @param index: The index of the endpoint.
+     * @param mock_response: The mock response configuration.
+     *
+     * # Returns
+     * @return The delay.
+     */
+    pub fn delay(&self, index: usize, mock_response: &MockResponseConfiguration) -> Duration {
+        sample_delay(mock_response, &self.generators[index])
+    }
+
+    /**
+     * Get the delay of the fallback response. The delay is the fixed delay plus a sample from the delay distribution.
+     *
+     * # Arguments
+     * @param fallback: The fallback response configuration.
+     *
+     * # Returns
+     * @return The delay.
+     */
+    pub fn fallback_delay(&self, fallback: &MockResponseConfiguration) -> Duration {
+        sample_delay(fallback, &self.fallback_generator)
+    }
+}
+
+/**
+ * Get the delay of the mock response with the random generator.
+ *
+ * # Arguments
+ * @param mock_response: The mock response configuration.
+ * @param generator: The random generator.
+ *
+ * # Returns
+ * @return The delay.
+ */
+fn sample_delay(mock_response: &MockResponseConfiguration, generator: &Mutex<StdRng>) -> Duration {
+    let random_delay = match &mock_response.delay_distribution {
+        Some(delay_distribution) => sample(delay_distribution, &mut generator.lock().unwrap_or_else(|err| err.into_inner())),
+        None => 0,
+    };
+    Duration::from_millis(mock_response.delay.saturating_add(random_delay))
+}
+
+/**
+ * Create a random generator for each endpoint. The seed is combined with a salt that is distinct for each user of
+ * the generators, so the delay, chaos and balancer generators of an endpoint do not repeat each other's random
+ * numbers when they share the server seed.
+ *
+ * # Arguments
+ * @param count: The number of endpoints.
+ * @param seed: The seed of the random generators. If None the generators are seeded from the operating system.
+ * @param salt: The seed salt of the user of the generators.
+ *
+ * # Returns
+ * @return The random generators by endpoint index.
+ */
+pub fn create_generators(count: usize, seed: Option<u64>, salt: u64) -> Vec<Mutex<StdRng>> {
+    (0..count)
+        .map(|index| match seed {
+            Some(seed) => StdRng::seed_from_u64((seed ^ salt).wrapping_add(index as u64)),
+            None => StdRng::from_entropy(),
+        })
+        .map(Mutex::new)
+        .collect()
+}
+
+/**
+ * Sample a delay from the distribution.
+ *
+ * # Arguments
+ * @param delay_distribution: The delay distribution.
+ * @param generator: The random generator.
+ *
+ * # Returns
+ * @return The delay in milliseconds.
+ */
+fn sample(delay_distribution: &DelayDistribution, generator: &mut StdRng) -> u64 {
+    let delay = match delay_distribution {
+        DelayDistribution::Uniform { min, max } => return generator.gen_range(*min..=*max),
+        DelayDistribution::Normal { mean, standard_deviation } => Normal::new(*mean, *standard_deviation).map_or(*mean, |normal| normal.sample(generator)),
+        DelayDistribution::LogNormal { median, sigma } => LogNormal::new(median.ln(), *sigma).map_or(*median, |log_normal| log_normal.sample(generator)),
+        DelayDistribution::Percentiles { p50, p95, p99 } => {
+            let points = [(0.0, 0.0), (0.5, *p50 as f64), (0.95, *p95 as f64), (0.99, *p99 as f64), (1.0, *p99 as f64)];
+            interpolate(generator.gen::<f64>(), &points)
+        }
+    };
+    delay.max(0.0).round() as u64
+}
+
+/**
+ * Find the delay of the quantile by linear interpolation between the percentile points.
+ *
+ * # Arguments
+ * @param quantile: The quantile between 0 and 1.
+ * @param points: The quantile and delay of each point, sorted by quantile.
+ *
+ * # Returns
+ * @return The delay.
+ */
+fn interpolate(quantile: f64, points: &[(f64, f64)]) -> f64 {
+    for window in points.windows(2) {
+        let ((start_quantile, start_delay), (end_quantile, end_delay)) = (window[0], window[1]);
+        if quantile <= end_quantile {
+            return start_delay + (end_delay - start_delay) * (quantile - start_quantile) / (end_quantile - start_quantile);
+        }
+    }
+    points.last().map_or(0.0, |(_, delay)| *delay)
+}
+
+/**
+ * Validate the delay distribution.
+ *
+ * # Arguments
+ * @param delay_distribution: The delay distribution.
+ *
+ * # Returns
+ * @return Ok if the delay distribution is valid.
+ *
+ * # Errors
+ * @return An error if min is greater than max.
+ * @return An error if the standard deviation or sigma is negative or not finite.
+ * @return An error if the mean is not finite or the median is not positive.
+ * @return An error if the percentiles are not increasing.
+ */
+fn validate(delay_distribution: &DelayDistribution) -> Result<(), ApplicationError> {
+    let valid = match delay_distribution {
+        DelayDistribution::Uniform { min, max } => min <= max,
+        DelayDistribution::Normal { mean, standard_deviation } => mean.is_finite() && standard_deviation.is_finite() && *standard_deviation >= 0.0,
+        DelayDistribution::LogNormal { median, sigma } => median.is_finite() && *median > 0.0 && sigma.is_finite() && *sigma >= 0.0,
+        DelayDistribution::Percentiles { p50, p95, p99 } => p50 <= p95 && p95 <= p99,
+    };
+    if !valid {
+        return Err(ApplicationError::ConfigurationError(format!("Invalid delay distribution: {:?}", delay_distribution)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /**
+     * Sample delays in milliseconds from the endpoint.
+     */
+    fn samples(sampler: &DelaySampler, endpoint: &EndpointConfiguration, count: usize) -> Vec<u64> {
+        (0..count).map(|_| sampler.delay(0, endpoint.mock_response.as_ref().unwrap()).as_millis() as u64).collect()
+    }
+
+    /**
+     * Verifying that seeded delays are reproducible and include the fixed delay.
+     */
+    #[test]
+    fn test_seed() {
+        let endpoints = vec![EndpointConfiguration::new("/test".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 10).with_delay_distribution(DelayDistribution::Uniform { min: 0, max: 1000 })), None)];
+        let first = samples(&DelaySampler::new(&endpoints, None, Some(42)).unwrap(), &endpoints[0], 20);
+        let second = samples(&DelaySampler::new(&endpoints, None, Some(42)).unwrap(), &endpoints[0], 20);
+        assert_eq!(first, second);
+        assert!(first.iter().all(|delay| (10..=1010).contains(delay)));
+    }
+
+    /**
+     * Verifying the percentiles of the sampled delays.
+     */
+    #[test]
+    fn test_percentiles() {
+        let endpoints = vec![EndpointConfiguration::new("/test".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 10).with_delay_distribution(DelayDistribution::Percentiles { p50: 100, p95: 500, p99: 1000 })), None)];
+        let mut delays = samples(&DelaySampler::new(&endpoints, None, Some(1)).unwrap(), &endpoints[0], 10000);
+        delays.sort();
+        assert!((90..=130).contains(&delays[5000]));
+        assert!((440..=600).contains(&delays[9500]));
+        assert!(delays[9999] <= 1010);
+        let endpoints = vec![EndpointConfiguration::new("/test".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 10).with_delay_distribution(DelayDistribution::LogNormal { median: 200.0, sigma: 0.5 })), None)];
+        let mut delays = samples(&DelaySampler::new(&endpoints, None, Some(1)).unwrap(), &endpoints[0], 10000);
+        delays.sort();
+        assert!((190..=230).contains(&delays[5000]));
+    }
+
+    /**
+     * Verifying that invalid distributions fail.
+     */
+    #[test]
+    fn test_invalid() {
+        let distributions = [
+            DelayDistribution::Uniform { min: 10, max: 5 },
+            DelayDistribution::Normal { mean: 10.0, standard_deviation: -1.0 },
+            DelayDistribution::LogNormal { median: 0.0, sigma: 1.0 },
+            DelayDistribution::Percentiles { p50: 100, p95: 50, p99: 200 },
+        ];
+        for distribution in distributions {
+            let endpoints = vec![EndpointConfiguration::new("/test".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 10).with_delay_distribution(distribution)), None)];
+            assert!(DelaySampler::new(&endpoints, None, None).is_err());
+        }
+    }
+}
@@ -1,11 +1,29 @@
+mod admin;
 mod args;
+mod balancer;
+mod body;
+mod chaos;
+mod fault;
+mod journal;
+mod latency;
+mod matcher;
+mod proxy;
+mod record;
+mod scenario;
+mod sequence;
 mod server;
+mod streaming;
+mod template;
+mod throttle;
+mod websocket;
+
+use std::path::Path;
 
 use clap::Parser;
 
 use args::Args;
 use server::ServerSetup;
-use testit_lib::{config::{AppConfiguration, TestConfiguration}, error::ApplicationError};
+use testit_lib::{config::{AppConfiguration, TestConfiguration}, error::ApplicationError, har::Har};
 
 /**
  * The main function for the testit-daemon application.
@@ -16,11 +34,12 @@ use testit_lib::{config::{AppConfiguration, TestConfiguration}, error::Applicati
 async fn main() -> Result<(), ApplicationError> {
     let args = Args::parse();
     let config = read_input_file(&args)?;
-    init(args, config).await?;
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        wait_for_terminate().await?
+    let server_setup = init(&args, &config).await?;
+    wait_for_terminate().await?;
+    if let Some(server_setup) = server_setup {
+        save_outputs(&args, &config, &server_setup)?;
     }
+    Ok(())
 }
 
 /**
@@ -48,7 +67,7 @@ fn read_input_file(args: &Args) -> Result<AppConfiguration, ApplicationError> {
  * @param config: The configuration to initialize the application with.
  * 
  * # Returns
- * @return Ok if the application was initialized successfully.
+ * @return The server setup if the daemon was started.
  * 
  * # Errors
  * @return An error if the daemon could not be started.
@@ -56,13 +75,14 @@ fn read_input_file(args: &Args) -> Result<AppConfiguration, ApplicationError> {
  * @return An error if the id is missing.
  * @return An error if the test is not found.
  */
-async fn init(args: Args, config: AppConfiguration) -> Result<(), ApplicationError> {
+async fn init(args: &Args, config: &AppConfiguration) -> Result<Option<ServerSetup>, ApplicationError> {
     if args.list {
-        list_tests(&config)?;
-    } else {
-        start_daemon(&args.id, &config).await?;
+        list_tests(config)?;
+        return Ok(None);
     }
-    Ok(())
+    let base_path = Path::new(&args.file).parent().unwrap_or(Path::new(""));
+    let server_setup = start_daemon(args, config, base_path).await?;
+    Ok(Some(server_setup))
 }
 
 /**
@@ -84,31 +104,89 @@ fn list_tests(config: &AppConfiguration) -> Result<(), ApplicationError> {
 }
 
 /**
- * Start the daemon with the specified id.
+ * Start the daemon with the id of the arguments.
  * 
  * # Arguments
- * @param id: The id of the test to start.
+ * @param args: The arguments with the id of the test, the admin port and if upstream responses are recorded.
  * @param config: The configuration to search for the test.
+ * @param base_path: The directory relative response files are resolved from.
  * 
  * # Returns
- * @return Ok if the daemon was started successfully.
+ * @return The server setup of the started servers.
  * 
  * # Errors
  * @return An error if the test is not found.
  * @return An error if the id is missing.
+ * @return An error if the admin server could not be started.
  */
-async fn start_daemon(id: &Option<String>, config: &AppConfiguration) -> Result<(), ApplicationError> {
-    let id = match id {
+async fn start_daemon(args: &Args, config: &AppConfiguration, base_path: &Path) -> Result<ServerSetup, ApplicationError> {
+    let id = match &args.id {
         Some(id) => id,
         None => { return Err(ApplicationError::MissingId("Missing id".to_string())); }
     };
     let test = get_test(id, config)?;
-    let mut server_setup = ServerSetup::new();
+    let mut server_setup = ServerSetup::new().with_base_path(base_path);
+    if args.record.is_some() {
+        server_setup = server_setup.with_recording();
+    }
     server_setup.setup_test(test).await;
     server_setup.start_servers().await.map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
+    if let Some(admin_port) = args.admin_port {
+        admin::start_admin_server(admin_port, server_setup.journal()).await?;
+    }
+    Ok(server_setup)
+}
+
+/**
+ * Save the files requested in the arguments when the daemon stops.
+ * 
+ * # Arguments
+ * @param args: The arguments with the HAR file and the record file.
+ * @param config: The configuration the daemon was started with.
+ * @param server_setup: The server setup of the stopped servers.
+ * 
+ * # Returns
+ * @return Ok if the files were saved.
+ * 
+ * # Errors
+ * @return An error if the HAR file could not be saved.
+ * @return An error if the recorded test could not be saved.
+ */
+fn save_outputs(args: &Args, config: &AppConfiguration, server_setup: &ServerSetup) -> Result<(), ApplicationError> {
+    if let Some(har_file) = &args.har_file {
+        Har::new(&server_setup.journal().entries()).save(har_file)?;
+    }
+    if let (Some(record_file), Some(recorder), Some(id)) = (&args.record, server_setup.recorder(), &args.id) {
+        let recorded_test = recorder.test_configuration(get_test(id, config)?);
+        save_recording(record_file, config, recorded_test)?;
+    }
     Ok(())
 }
 
+/**
+ * Save the recorded test. The test is added to the configuration in the file if it exists.
+ * 
+ * # Arguments
+ * @param path: The file to save the recorded test to.
+ * @param config: The configuration the daemon was started with. Used for the name of a new configuration.
+ * @param recorded_test: The recorded test.
+ * 
+ * # Returns
+ * @return Ok if the recorded test was saved.
+ * 
+ * # Errors
+ * @return An error if the existing file could not be read.
+ * @return An error if the file could not be written.
+ */
+fn save_recording(path: &str, config: &AppConfiguration, recorded_test: TestConfiguration) -> Result<(), ApplicationError> {
+    let mut recording = match Path::new(path).exists() {
+        true => AppConfiguration::load(path)?,
+        false => AppConfiguration::new(config.name.clone(), config.description.clone(), vec![]),
+    };
+    recording.tests.push(recorded_test);
+    recording.save(path)
+}
+
 /**
  * Get the test with the specified id.
  * 
@@ -136,8 +214,6 @@ fn get_test<'a>(id: &str, config: &'a AppConfiguration) -> Result<&'a TestConfig
  */
 #[cfg(unix)]
 async fn wait_for_terminate() -> Result<(), ApplicationError> {
-    use std::process::exit;
-
     use tokio::signal::unix::{signal, SignalKind};
 
     // Infos here:
@@ -146,9 +222,10 @@ async fn wait_for_terminate() -> Result<(), ApplicationError> {
     let mut signal_interrupt = signal(SignalKind::interrupt()).map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
 
     tokio::select! {
-        _ = signal_terminate.recv() => exit(0),
-        _ = signal_interrupt.recv() => exit(0),
+        _ = signal_terminate.recv() => {},
+        _ = signal_interrupt.recv() => {},
     };
+    Ok(())
 }
 
 /**
@@ -156,8 +233,6 @@ async fn wait_for_terminate() -> Result<(), ApplicationError> {
  */
 #[cfg(windows)]
 async fn wait_for_terminate() -> Result<(), ApplicationError> {
-    use std::process::exit;
-
     use tokio::signal::windows;
 
     // Infos here:
@@ -168,11 +243,12 @@ async fn wait_for_terminate() -> Result<(), ApplicationError> {
     let mut signal_shutdown = windows::ctrl_shutdown().map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
 
     tokio::select! {
-        _ = signal_c.recv() => exit(0),
-        _ = signal_break.recv() => exit(0),
-        _ = signal_close.recv() => exit(0),
-        _ = signal_shutdown.recv() => exit(0),
+        _ = signal_c.recv() => {},
+        _ = signal_break.recv() => {},
+        _ = signal_close.recv() => {},
+        _ = signal_shutdown.recv() => {},
     };
+    Ok(())
 }
 
 
@@ -0,0 +1,822 @@
+use std::{cell::OnceCell, collections::HashMap};
+
+use actix_web::{web, HttpRequest};
+use regex::{Captures, Regex, RegexSet};
+use serde::Serialize;
+use serde_json::Value;
+use jsonpath_rust::{parser::{model::JpQuery, parse_json_path}, query::js_path_process};
+use sxd_document::Package;
+use sxd_xpath::{Context, Factory};
+use testit_lib::{config::{BodyMatcherConfiguration, EndpointConfiguration, MatchRule}, error::ApplicationError};
+
+use crate::scenario::ScenarioStates;
+
+/**
+ * The maximum number of near misses reported for an unmatched request.
+ */
+const MAX_NEAR_MISSES: usize = 3;
+
+/**
+ * The name of the path criterion in near misses.
+ */
+const PATH_CRITERION: &str = "path";
+
+/**
+ * The EndpointMatcher is used to find the endpoint matching a request.
+ * All endpoint patterns are compiled once when the server starts.
+ */
+pub struct EndpointMatcher {
+    // The compiled endpoint paths. The index of a pattern is the index of the endpoint.
+    paths: RegexSet,
+    // The compiled endpoints in configuration order.
+    endpoints: Vec<CompiledEndpoint>,
+}
+
+impl EndpointMatcher {
+    /**
+     * Create a new endpoint matcher.
+     *
+     * # Arguments
+     * @param endpoints: The endpoint configurations.
+     *
+     * # Returns
+     * @return The endpoint matcher.
+     *
+     * # Errors
+     * @return An error if an endpoint pattern is not a valid regular expression.
+     */
+    pub fn new(endpoints: &[EndpointConfiguration]) -> Result<Self, ApplicationError> {
+        let compiled_endpoints = endpoints
+            .iter()
+            .map(CompiledEndpoint::new)
+            .collect::<Result<Vec<CompiledEndpoint>, ApplicationError>>()?;
+        let paths = RegexSet::new(endpoints.iter().map(|endpoint| endpoint.endpoint.as_str())).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?;
+        Ok(EndpointMatcher {
+            paths,
+            endpoints: compiled_endpoints,
+        })
+    }
+
+    /**
+     * Find the first endpoint matching the request.
+     *
+     * # Arguments
+     * @param request: The request.
+     * @param body: The request body.
+     * @param scenarios: The current scenario states.
+     *
+     * # Returns
+     * @return The index of the matching endpoint.
+     */
+    pub fn find(&self, request: &HttpRequest, body: &[u8], scenarios: &ScenarioStates) -> Option<usize> {
+        let context = MatchContext::new(request, body, scenarios);
+        self.paths
+            .matches(request.uri().path())
+            .into_iter()
+            .find(|index| self.endpoints[*index].is_match(&context))
+    }
+
+    /**
+     * Find the first endpoint matching the request without checking the body matchers. Used before the request body
+     * is read, so endpoints that only differ by their body matchers resolve to the first of them.
+     *
+     * # Arguments
+     * @param request: The request.
+     * @param scenarios: The current scenario states.
+     *
+     * # Returns
+     * @return The index of the endpoint.
+     */
+    pub fn find_before_body(&self, request: &HttpRequest, scenarios: &ScenarioStates) -> Option<usize> {
+        let context = MatchContext::new(request, &[], scenarios);
+        self.paths
+            .matches(request.uri().path())
+            .into_iter()
+            .find(|index| self.endpoints[*index].is_match_without_body(&context))
+    }
+
+    /**
+     * Find out why no endpoint matched the request.
+     *
+     * # Arguments
+     * @param request: The request.
+     * @param body: The request body.
+     * @param scenarios: The current scenario states.
+     *
+     * # Returns
+     * @return The allowed methods if only the method did not match, and the closest endpoints.
+     */
+    pub fn diagnose(&self, request: &HttpRequest, body: &[u8], scenarios: &ScenarioStates) -> Diagnosis {
+        let context = MatchContext::new(request, body, scenarios);
+        let mut path_methods: Vec<String> = Vec::new();
+        for index in self.paths.matches(request.uri().path()).into_iter() {
+            if !path_methods.contains(&self.endpoints[index].method) {
+                path_methods.push(self.endpoints[index].method.clone());
+            }
+        }
+        let allowed_methods = match path_methods.iter().any(|method| method.as_str() == request.method().as_str()) {
+            true => vec![],
+            false => path_methods,
+        };
+        let mut near_misses: Vec<NearMiss> = self.endpoints.iter().map(|endpoint| endpoint.near_miss(&context)).collect();
+        near_misses.sort_by_key(|near_miss| (near_miss.failed.iter().any(|criterion| criterion == PATH_CRITERION), near_miss.failed.len()));
+        near_misses.truncate(MAX_NEAR_MISSES);
+        Diagnosis { allowed_methods, near_misses }
+    }
+
+    /**
+     * Get the capture groups of the endpoint regular expression.
+     *
+     * # Arguments
+     * @param index: The index of the endpoint.
+     * @param path: The request path.
+     *
+     * # Returns
+     * @return The capture groups if the path matches the endpoint.
+     */
+    pub fn captures<'p>(&self, index: usize, path: &'p str) -> Option<Captures<'p>> {
+        self.endpoints.get(index).and_then(|endpoint| endpoint.path.captures(path))
+    }
+}
+
+/**
+ * The reasons a request did not match any endpoint.
+ */
+pub struct Diagnosis {
+    // The methods of the endpoints matching the path. Empty if no path matched or an endpoint with the request method matched the path.
+    pub allowed_methods: Vec<String>,
+    // The closest endpoints with the fewest failed criteria first.
+    pub near_misses: Vec<NearMiss>,
+}
+
+/**
+ * An endpoint that did not match a request and the criteria that failed.
+ */
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NearMiss {
+    // The ID of the endpoint.
+    pub endpoint_id: String,
+    // The endpoint path regular expression.
+    pub endpoint: String,
+    // The HTTP method of the endpoint.
+    pub method: String,
+    // The criteria that failed, such as path, method, soapAction, header or body.
+    pub failed: Vec<String>,
+}
+
+/**
+ * The request data used for matching. Data that is expensive to extract is only extracted once per request.
+ */
+struct MatchContext<'a> {
+    // The request.
+    request: &'a HttpRequest,
+    // The decoded query parameters.
+    query: Vec<(String, String)>,
+    // The request body.
+    body: &'a [u8],
+    // The body parsed as JSON. This is parsed the first time a JSON body matcher is used.
+    json: OnceCell<Option<Value>>,
+    // The body parsed as XML. This is parsed the first time an XPath body matcher is used.
+    xml: OnceCell<Option<Package>>,
+    // The current scenario states.
+    scenarios: &'a ScenarioStates,
+}
+
+impl<'a> MatchContext<'a> {
+    /**
+     * Create a new match context.
+     *
+     * # Arguments
+     * @param request: The request.
+     * @param body: The request body.
+     * @param scenarios: The current scenario states.
+     *
+     * # Returns
+     * @return The match context.
+     */
+    fn new(request: &'a HttpRequest, body: &'a [u8], scenarios: &'a ScenarioStates) -> Self {
+        let query = web::Query::<Vec<(String, String)>>::from_query(request.query_string())
+            .map(|query| query.into_inner())
+            .unwrap_or_default();
+        MatchContext {
+            request,
+            query,
+            body,
+            json: OnceCell::new(),
+            xml: OnceCell::new(),
+            scenarios,
+        }
+    }
+
+    /**
+     * Get the body parsed as JSON.
+     *
+     * # Returns
+     * @return The JSON value, or None if the body is not valid JSON.
+     */
+    fn json(&self) -> Option<&Value> {
+        self.json.get_or_init(|| serde_json::from_slice(self.body).ok()).as_ref()
+    }
+
+    /**
+     * Get the body parsed as XML.
+     *
+     * # Returns
+     * @return The XML document, or None if the body is not valid XML.
+     */
+    fn xml(&self) -> Option<&Package> {
+        self.xml.get_or_init(|| {
+            let body = std::str::from_utf8(self.body).ok()?;
+            sxd_document::parser::parse(body).ok()
+        }).as_ref()
+    }
+}
+
+/**
+ * The compiled match criteria for a single endpoint.
+ */
+struct CompiledEndpoint {
+    // The ID of the endpoint.
+    id: String,
+    // The endpoint path. Matching is done with the regex set, but this is used for capture groups.
+    path: Regex,
+    // The HTTP method.
+    method: String,
+    // The SOAP action.
+    soap_action: Option<String>,
+    // The header names and the rules they must match.
+    headers: Vec<(String, ValueMatcher)>,
+    // The query parameter names and the rules they must match.
+    query: Vec<(String, ValueMatcher)>,
+    // The rules the body must match.
+    body: Vec<BodyMatcher>,
+    // The scenario name and the state it must be in.
+    scenario_state: Option<(String, String)>,
+}
+
+impl CompiledEndpoint {
+    /**
+     * Compile the endpoint configuration.
+     *
+     * # Arguments
+     * @param endpoint: The endpoint configuration.
+     *
+     * # Returns
+     * @return The compiled endpoint.
+     *
+     * # Errors
+     * @return An error if the endpoint pattern is not a valid regular expression.
+     * @return An error if a header pattern is not a valid regular expression.
+     * @return An error if a query parameter pattern is not a valid regular expression.
+     * @return An error if a body matcher expression is invalid.
+     */
+    fn new(endpoint: &EndpointConfiguration) -> Result<Self, ApplicationError> {
+        let path = Regex::new(&endpoint.endpoint).map_err(|err| ApplicationError::ConfigurationError(format!("Invalid endpoint {} for {}: {}", endpoint.endpoint, endpoint.id, err)))?;
+        let headers = endpoint.header_matchers
+            .iter()
+            .map(|header_matcher| Ok((header_matcher.name.to_ascii_lowercase(), ValueMatcher::new(&header_matcher.rule)?)))
+            .collect::<Result<Vec<(String, ValueMatcher)>, ApplicationError>>()?;
+        let query = endpoint.query_matchers
+            .iter()
+            .map(|query_matcher| Ok((query_matcher.name.clone(), ValueMatcher::new(&query_matcher.rule)?)))
+            .collect::<Result<Vec<(String, ValueMatcher)>, ApplicationError>>()?;
+        let body = endpoint.body_matchers
+            .iter()
+            .map(BodyMatcher::new)
+            .collect::<Result<Vec<BodyMatcher>, ApplicationError>>()?;
+        Ok(CompiledEndpoint {
+            id: endpoint.id.clone(),
+            path,
+            method: endpoint.method.clone(),
+            soap_action: endpoint.soap_action.as_ref().map(|soap_action| unquote(soap_action).to_string()),
+            headers,
+            query,
+            body,
+            scenario_state: endpoint.scenario.as_ref().and_then(|scenario| Some((scenario.name.clone(), scenario.required_state.clone()?))),
+        })
+    }
+
+    /**
+     * Check if the request matches the endpoint criteria.
+     *
+     * # Arguments
+     * @param context: The request data used for matching.
+     *
+     * # Returns
+     * @return True if the request matches.
+     */
+    fn is_match(&self, context: &MatchContext) -> bool {
+        self.is_match_without_body(context) && self.body.iter().all(|body_matcher| body_matcher.is_match(context))
+    }
+
+    /**
+     * Check if the request matches all criteria of the endpoint except the body matchers.
+     *
+     * # Arguments
+     * @param context: The request data used for matching.
+     *
+     * # Returns
+     * @return True if the request matches.
+     */
+    fn is_match_without_body(&self, context: &MatchContext) -> bool {
+        context.request.method().as_str() == self.method.as_str()
+            && self.is_soap_action_match(context.request)
+            && self.is_headers_match(context.request)
+            && self.is_query_match(&context.query)
+            && self.scenario_state.as_ref().is_none_or(|(name, state)| context.scenarios.is_state(name, state))
+    }
+
+    /**
+     * Check all criteria of the endpoint and collect the ones that failed.
+     *
+     * # Arguments
+     * @param context: The request data used for matching.
+     *
+     * # Returns
+     * @return The endpoint and the failed criteria.
+     */
+    fn near_miss(&self, context: &MatchContext) -> NearMiss {
+        let mut failed = Vec::new();
+        if !self.path.is_match(context.request.uri().path()) {
+            failed.push(PATH_CRITERION.to_string());
+        }
+        if context.request.method().as_str() != self.method.as_str() {
+            failed.push("method".to_string());
+        }
+        if !self.is_soap_action_match(context.request) {
+            failed.push("soapAction".to_string());
+        }
+        for (name, value_matcher) in self.headers.iter() {
+            if !value_matcher.is_match(context.request.headers().get_all(name.as_str()).filter_map(|value| value.to_str().ok())) {
+                failed.push(format!("header {}", name));
+            }
+        }
+        for (name, value_matcher) in self.query.iter() {
+            if !value_matcher.is_match(context.query.iter().filter(|(key, _)| key == name).map(|(_, value)| value.as_str())) {
+                failed.push(format!("query {}", name));
+            }
+        }
+        if !self.body.iter().all(|body_matcher| body_matcher.is_match(context)) {
+            failed.push("body".to_string());
+        }
+        if let Some((name, state)) = self.scenario_state.as_ref().filter(|(name, state)| !context.scenarios.is_state(name, state)) {
+            failed.push(format!("scenario {} in state {}", name, state));
+        }
+        NearMiss {
+            endpoint_id: self.id.clone(),
+            endpoint: self.path.as_str().to_string(),
+            method: self.method.clone(),
+            failed,
+        }
+    }
+
+    /**
+     * Check if the request headers match all header rules.
+     *
+     * # Arguments
+     * @param request: The request.
+     *
+     * # Returns
+     * @return True if all header rules match.
+     */
+    fn is_headers_match(&self, request: &HttpRequest) -> bool {
+        self.headers.iter().all(|(name, value_matcher)| {
+            value_matcher.is_match(request.headers().get_all(name.as_str()).filter_map(|value| value.to_str().ok()))
+        })
+    }
+
+    /**
+     * Check if the query parameters match all query parameter rules.
+     *
+     * # Arguments
+     * @param query: The decoded query parameters.
+     *
+     * # Returns
+     * @return True if all query parameter rules match.
+     */
+    fn is_query_match(&self, query: &[(String, String)]) -> bool {
+        self.query.iter().all(|(name, value_matcher)| {
+            value_matcher.is_match(query.iter().filter(|(key, _)| key == name).map(|(_, value)| value.as_str()))
+        })
+    }
+
+    /**
+     * Check if the SOAP action of the request matches the endpoint. Endpoints without a SOAP action match all requests.
+     *
+     * # Arguments
+     * @param request: The request.
+     *
+     * # Returns
+     * @return True if the SOAP action matches.
+     */
+    fn is_soap_action_match(&self, request: &HttpRequest) -> bool {
+        match &self.soap_action {
+            Some(soap_action) => soap_action_of(request).as_deref() == Some(soap_action.as_str()),
+            None => true,
+        }
+    }
+}
+
+/**
+ * A compiled match rule for request values.
+ */
+enum ValueMatcher {
+    // The value must be equal.
+    Equals(String),
+    // The value must match the regular expression.
+    Regex(Regex),
+    // All the values must be present.
+    Includes(Vec<String>),
+    // The value must be present.
+    Present,
+    // The value must be absent.
+    Absent,
+}
+
+impl ValueMatcher {
+    /**
+     * Compile the match rule.
+     *
+     * # Arguments
+     * @param rule: The match rule.
+     *
+     * # Returns
+     * @return The compiled match rule.
+     *
+     * # Errors
+     * @return An error if the pattern is not a valid regular expression.
+     */
+    fn new(rule: &MatchRule) -> Result<Self, ApplicationError> {
+        Ok(match rule {
+            MatchRule::Equals { value } => ValueMatcher::Equals(value.clone()),
+            MatchRule::Regex { pattern } => ValueMatcher::Regex(Regex::new(pattern).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?),
+            MatchRule::Includes { values } => ValueMatcher::Includes(values.clone()),
+            MatchRule::Present => ValueMatcher::Present,
+            MatchRule::Absent => ValueMatcher::Absent,
+        })
+    }
+
+    /**
+     * Check if the values match the rule. Equals and regex rules match if any of the values match, while
+     * includes rules match if all the expected values are among the values.
+     *
+     * # Arguments
+     * @param values: The values of the request.
+     *
+     * # Returns
+     * @return True if the values match.
+     */
+    fn is_match<'a>(&self, mut values: impl Iterator<Item = &'a str>) -> bool {
+        match self {
+            ValueMatcher::Equals(expected) => values.any(|value| value == expected),
+            ValueMatcher::Regex(regex) => values.any(|value| regex.is_match(value)),
+            ValueMatcher::Includes(expected) => {
+                let values: Vec<&str> = values.collect();
+                expected.iter().all(|expected| values.contains(&expected.as_str()))
+            }
+            ValueMatcher::Present => values.next().is_some(),
+            ValueMatcher::Absent => values.next().is_none(),
+        }
+    }
+}
+
+/**
+ * A compiled match rule for the request body.
+ */
+enum BodyMatcher {
+    // The body must be equal to the JSON value.
+    EqualToJson { json: Value, ignore_extra_fields: bool },
+    // The JSONPath expression must select at least one value.
+    JsonPath(JpQuery),
+    // The XPath expression must select at least one node or evaluate to true. The expression is validated
+    // at startup, but compiled for each request since compiled XPath expressions can not be shared between threads.
+    XPath { expression: String, namespaces: HashMap<String, String> },
+    // The body must match the regular expression.
+    Regex(Regex),
+}
+
+impl BodyMatcher {
+    /**
+     * Compile the body matcher configuration.
+     *
+     * # Arguments
+     * @param body_matcher: The body matcher configuration.
+     *
+     * # Returns
+     * @return The compiled body matcher.
+     *
+     * # Errors
+     * @return An error if the expression or pattern is invalid.
+     */
+    fn new(body_matcher: &BodyMatcherConfiguration) -> Result<Self, ApplicationError> {
+        Ok(match body_matcher {
+            BodyMatcherConfiguration::EqualToJson { json, ignore_extra_fields } => BodyMatcher::EqualToJson { json: json.clone(), ignore_extra_fields: *ignore_extra_fields },
+            BodyMatcherConfiguration::JsonPath { expression } => BodyMatcher::JsonPath(parse_json_path(expression).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?),
+            BodyMatcherConfiguration::XPath { expression, namespaces } => {
+                Factory::new()
+                    .build(expression)
+                    .map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?
+                    .ok_or(ApplicationError::ConfigurationError(format!("Empty XPath expression: {}", expression)))?;
+                BodyMatcher::XPath { expression: expression.clone(), namespaces: namespaces.clone() }
+            }
+            BodyMatcherConfiguration::Regex { pattern } => BodyMatcher::Regex(Regex::new(pattern).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?),
+        })
+    }
+
+    /**
+     * Check if the request body matches the rule.
+     *
+     * # Arguments
+     * @param context: The request data used for matching.
+     *
+     * # Returns
+     * @return True if the body matches.
+     */
+    fn is_match(&self, context: &MatchContext) -> bool {
+        match self {
+            BodyMatcher::EqualToJson { json, ignore_extra_fields } => context.json().is_some_and(|actual| is_json_equal(json, actual, *ignore_extra_fields)),
+            BodyMatcher::JsonPath(json_path) => context.json().is_some_and(|actual| js_path_process(json_path, actual).is_ok_and(|values| !values.is_empty())),
+            BodyMatcher::XPath { expression, namespaces } => context.xml().is_some_and(|package| is_xpath_match(expression, namespaces, package)),
+            BodyMatcher::Regex(regex) => regex.is_match(&String::from_utf8_lossy(context.body)),
+        }
+    }
+}
+
+/**
+ * Check if the JSON values are equal.
+ *
+ * # Arguments
+ * @param expected: The expected JSON value.
+ * @param actual: The actual JSON value.
+ * @param ignore_extra_fields: If fields in the actual objects that are not in the expected objects are ignored.
+ *
+ * # Returns
+ * @return True if the values are equal.
+ */
+fn is_json_equal(expected: &Value, actual: &Value, ignore_extra_fields: bool) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            (ignore_extra_fields || expected.len() == actual.len())
+                && expected.iter().all(|(key, value)| actual.get(key).is_some_and(|actual| is_json_equal(value, actual, ignore_extra_fields)))
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().zip(actual.iter()).all(|(expected, actual)| is_json_equal(expected, actual, ignore_extra_fields))
+        }
+        _ => expected == actual,
+    }
+}
+
+/**
+ * Check if the XPath expression selects at least one node or evaluates to true.
+ *
+ * # Arguments
+ * @param expression: The XPath expression.
+ * @param namespaces: The namespace prefixes used in the expression.
+ * @param package: The XML document.
+ *
+ * # Returns
+ * @return True if the expression matches.
+ */
+fn is_xpath_match(expression: &str, namespaces: &HashMap<String, String>, package: &Package) -> bool {
+    let Ok(Some(xpath)) = Factory::new().build(expression) else {
+        return false;
+    };
+    let mut context = Context::new();
+    for (prefix, uri) in namespaces.iter() {
+        context.set_namespace(prefix, uri);
+    }
+    let document = package.as_document();
+    match xpath.evaluate(&context, document.root()) {
+        Ok(sxd_xpath::Value::Nodeset(nodes)) => nodes.size() > 0,
+        Ok(sxd_xpath::Value::Boolean(value)) => value,
+        Ok(sxd_xpath::Value::String(value)) => !value.is_empty(),
+        Ok(sxd_xpath::Value::Number(value)) => !value.is_nan(),
+        Err(_) => false,
+    }
+}
+
+/**
+ * Get the SOAP action of the request. SOAP 1.1 uses the SOAPAction header, while SOAP 1.2 uses the
+ * action parameter of the application/soap+xml content type.
+ *
+ * # Arguments
+ * @param request: The request.
+ *
+ * # Returns
+ * @return The SOAP action without quotes.
+ */
+fn soap_action_of(request: &HttpRequest) -> Option<String> {
+    if let Some(soap_action) = request.headers().get("soapaction").and_then(|value| value.to_str().ok()) {
+        return Some(unquote(soap_action).to_string());
+    }
+    let content_type = request.headers().get("content-type").and_then(|value| value.to_str().ok())?;
+    let mut parts = content_type.split(';');
+    if !parts.next()?.trim().eq_ignore_ascii_case("application/soap+xml") {
+        return None;
+    }
+    parts
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("action"))
+        .map(|(_, value)| unquote(value).to_string())
+}
+
+/**
+ * Remove surrounding whitespace and quotes.
+ *
+ * # Arguments
+ * @param value: The value.
+ *
+ * # Returns
+ * @return The unquoted value.
+ */
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches('"')
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+    use testit_lib::config::{HeaderMatcherConfiguration, QueryMatcherConfiguration, ScenarioConfiguration, ScenarioStepConfiguration};
+    use serde_json::json;
+
+    use super::*;
+
+    /**
+     * Verifying that the first matching endpoint is found.
+     */
+    #[test]
+    fn test_find() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/test$".to_string(), "POST".to_string(), None, None, None),
+            EndpointConfiguration::new("^/test$".to_string(), "GET".to_string(), None, None, None),
+            EndpointConfiguration::new("^/test/.*".to_string(), "GET".to_string(), None, None, None),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        assert_eq!(matcher.find(&TestRequest::get().uri("/test").to_http_request(), &[], &ScenarioStates::new()), Some(1));
+        assert_eq!(matcher.find(&TestRequest::post().uri("/test").to_http_request(), &[], &ScenarioStates::new()), Some(0));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/test/1").to_http_request(), &[], &ScenarioStates::new()), Some(2));
+        assert_eq!(matcher.find(&TestRequest::delete().uri("/test").to_http_request(), &[], &ScenarioStates::new()), None);
+        assert_eq!(matcher.find_before_body(&TestRequest::get().uri("/test/1").to_http_request(), &ScenarioStates::new()), Some(2));
+        assert_eq!(matcher.find_before_body(&TestRequest::delete().uri("/test").to_http_request(), &ScenarioStates::new()), None);
+    }
+
+    /**
+     * Verifying the allowed methods and the failed criteria of the closest endpoints.
+     */
+    #[test]
+    fn test_diagnose() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/other$".to_string(), "GET".to_string(), None, None, None),
+            EndpointConfiguration::new("^/test$".to_string(), "POST".to_string(), None, None, None)
+                .with_header_matchers(vec![HeaderMatcherConfiguration::new("x-tenant".to_string(), MatchRule::Present)]),
+            EndpointConfiguration::new("^/test$".to_string(), "PUT".to_string(), None, None, None),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        let diagnosis = matcher.diagnose(&TestRequest::get().uri("/test").to_http_request(), &[], &ScenarioStates::new());
+        assert_eq!(diagnosis.allowed_methods, vec!["POST", "PUT"]);
+        assert_eq!(diagnosis.near_misses.iter().map(|near_miss| near_miss.endpoint_id.as_str()).collect::<Vec<&str>>(), vec![endpoints[2].id.as_str(), endpoints[1].id.as_str(), endpoints[0].id.as_str()]);
+        assert_eq!(diagnosis.near_misses[1].failed, vec!["method", "header x-tenant"]);
+        assert_eq!(diagnosis.near_misses[2].failed, vec!["path"]);
+        let diagnosis = matcher.diagnose(&TestRequest::post().uri("/test").to_http_request(), &[], &ScenarioStates::new());
+        assert!(diagnosis.allowed_methods.is_empty());
+        assert_eq!(diagnosis.near_misses[0].failed, vec!["header x-tenant"]);
+        assert!(matcher.diagnose(&TestRequest::get().uri("/missing").to_http_request(), &[], &ScenarioStates::new()).allowed_methods.is_empty());
+    }
+
+    /**
+     * Verifying SOAP 1.1 and SOAP 1.2 action matching.
+     */
+    #[test]
+    fn test_soap_action() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/soap$".to_string(), "POST".to_string(), Some("urn:getOrder".to_string()), None, None),
+            EndpointConfiguration::new("^/soap$".to_string(), "POST".to_string(), Some("\"urn:createOrder\"".to_string()), None, None),
+            EndpointConfiguration::new("^/soap$".to_string(), "POST".to_string(), None, None, None),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        assert_eq!(matcher.find(&TestRequest::post().uri("/soap").insert_header(("SOAPAction", "\"urn:getOrder\"")).to_http_request(), &[], &ScenarioStates::new()), Some(0));
+        assert_eq!(matcher.find(&TestRequest::post().uri("/soap").insert_header(("SOAPAction", "urn:createOrder")).to_http_request(), &[], &ScenarioStates::new()), Some(1));
+        assert_eq!(matcher.find(&TestRequest::post().uri("/soap").insert_header(("Content-Type", "application/soap+xml; charset=utf-8; action=\"urn:createOrder\"")).to_http_request(), &[], &ScenarioStates::new()), Some(1));
+        assert_eq!(matcher.find(&TestRequest::post().uri("/soap").insert_header(("Content-Type", "text/xml; action=\"urn:getOrder\"")).to_http_request(), &[], &ScenarioStates::new()), Some(2));
+        assert_eq!(matcher.find(&TestRequest::post().uri("/soap").insert_header(("SOAPAction", "urn:deleteOrder")).to_http_request(), &[], &ScenarioStates::new()), Some(2));
+    }
+
+    /**
+     * Verifying header matching.
+     */
+    #[test]
+    fn test_headers() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/test$".to_string(), "GET".to_string(), None, None, None).with_header_matchers(vec![
+                HeaderMatcherConfiguration::new("X-Tenant".to_string(), MatchRule::Equals { value: "acme".to_string() }),
+                HeaderMatcherConfiguration::new("Accept".to_string(), MatchRule::Regex { pattern: "^application/(.*\\+)?json".to_string() }),
+            ]),
+            EndpointConfiguration::new("^/test$".to_string(), "GET".to_string(), None, None, None).with_header_matchers(vec![
+                HeaderMatcherConfiguration::new("Authorization".to_string(), MatchRule::Present),
+            ]),
+            EndpointConfiguration::new("^/test$".to_string(), "GET".to_string(), None, None, None).with_header_matchers(vec![
+                HeaderMatcherConfiguration::new("x-tenant".to_string(), MatchRule::Absent),
+            ]),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        assert_eq!(matcher.find(&TestRequest::get().uri("/test").insert_header(("x-tenant", "acme")).insert_header(("accept", "application/vnd.api+json")).to_http_request(), &[], &ScenarioStates::new()), Some(0));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/test").insert_header(("x-tenant", "acme")).insert_header(("accept", "text/xml")).insert_header(("authorization", "Bearer x")).to_http_request(), &[], &ScenarioStates::new()), Some(1));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/test").to_http_request(), &[], &ScenarioStates::new()), Some(2));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/test").insert_header(("x-tenant", "other")).to_http_request(), &[], &ScenarioStates::new()), None);
+    }
+
+    /**
+     * Verifying query parameter matching.
+     */
+    #[test]
+    fn test_query() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/search$".to_string(), "GET".to_string(), None, None, None).with_query_matchers(vec![
+                QueryMatcherConfiguration::new("q".to_string(), MatchRule::Equals { value: "a b".to_string() }),
+            ]),
+            EndpointConfiguration::new("^/search$".to_string(), "GET".to_string(), None, None, None).with_query_matchers(vec![
+                QueryMatcherConfiguration::new("tag".to_string(), MatchRule::Includes { values: vec!["x".to_string(), "y".to_string()] }),
+            ]),
+            EndpointConfiguration::new("^/search$".to_string(), "GET".to_string(), None, None, None).with_query_matchers(vec![
+                QueryMatcherConfiguration::new("q".to_string(), MatchRule::Regex { pattern: "^[0-9]+$".to_string() }),
+                QueryMatcherConfiguration::new("debug".to_string(), MatchRule::Absent),
+            ]),
+            EndpointConfiguration::new("^/search$".to_string(), "GET".to_string(), None, None, None).with_query_matchers(vec![
+                QueryMatcherConfiguration::new("q".to_string(), MatchRule::Present),
+            ]),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        assert_eq!(matcher.find(&TestRequest::get().uri("/search?q=a%20b").to_http_request(), &[], &ScenarioStates::new()), Some(0));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/search?tag=y&tag=z&tag=x").to_http_request(), &[], &ScenarioStates::new()), Some(1));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/search?q=12").to_http_request(), &[], &ScenarioStates::new()), Some(2));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/search?q=12&debug=true&tag=x").to_http_request(), &[], &ScenarioStates::new()), Some(3));
+        assert_eq!(matcher.find(&TestRequest::get().uri("/search?tag=x").to_http_request(), &[], &ScenarioStates::new()), None);
+    }
+
+    /**
+     * Verifying body matching.
+     */
+    #[test]
+    fn test_body() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/order$".to_string(), "POST".to_string(), None, None, None).with_body_matchers(vec![
+                BodyMatcherConfiguration::EqualToJson { json: json!({ "id": 1, "lines": [{ "sku": "a" }] }), ignore_extra_fields: true },
+            ]),
+            EndpointConfiguration::new("^/order$".to_string(), "POST".to_string(), None, None, None).with_body_matchers(vec![
+                BodyMatcherConfiguration::EqualToJson { json: json!({ "id": 2 }), ignore_extra_fields: false },
+            ]),
+            EndpointConfiguration::new("^/order$".to_string(), "POST".to_string(), None, None, None).with_body_matchers(vec![
+                BodyMatcherConfiguration::JsonPath { expression: "$.lines[?@.sku == 'b']".to_string() },
+            ]),
+            EndpointConfiguration::new("^/order$".to_string(), "POST".to_string(), None, None, None).with_body_matchers(vec![
+                BodyMatcherConfiguration::XPath { expression: "//s:Body/o:GetOrder[o:id = '3']".to_string(), namespaces: HashMap::from([
+                    ("s".to_string(), "http://schemas.xmlsoap.org/soap/envelope/".to_string()),
+                    ("o".to_string(), "urn:orders".to_string()),
+                ]) },
+            ]),
+            EndpointConfiguration::new("^/order$".to_string(), "POST".to_string(), None, None, None).with_body_matchers(vec![
+                BodyMatcherConfiguration::Regex { pattern: "^id=[0-9]+$".to_string() },
+            ]),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        let request = TestRequest::post().uri("/order").to_http_request();
+        let scenarios = ScenarioStates::new();
+        assert_eq!(matcher.find(&request, br#"{ "id": 1, "lines": [{ "sku": "a", "count": 2 }], "extra": true }"#, &scenarios), Some(0));
+        assert_eq!(matcher.find(&request, br#"{ "id": 1, "lines": [{ "sku": "a" }, { "sku": "b" }] }"#, &scenarios), Some(2));
+        assert_eq!(matcher.find(&request, br#"{ "id": 2 }"#, &scenarios), Some(1));
+        assert_eq!(matcher.find(&request, br#"{ "id": 2, "extra": true }"#, &scenarios), None);
+        let soap = r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><o:GetOrder xmlns:o="urn:orders"><o:id>3</o:id></o:GetOrder></s:Body></s:Envelope>"#;
+        assert_eq!(matcher.find(&request, soap.as_bytes(), &scenarios), Some(3));
+        assert_eq!(matcher.find(&request, soap.replace(">3<", ">4<").as_bytes(), &scenarios), None);
+        assert_eq!(matcher.find(&request, b"id=12", &scenarios), Some(4));
+    }
+
+    /**
+     * Verifying scenario state matching.
+     */
+    #[test]
+    fn test_scenario() {
+        let endpoints = vec![
+            EndpointConfiguration::new("^/order$".to_string(), "GET".to_string(), None, None, None).with_scenario(ScenarioStepConfiguration::new("order".to_string(), Some("created".to_string()), None)),
+            EndpointConfiguration::new("^/order$".to_string(), "GET".to_string(), None, None, None).with_scenario(ScenarioStepConfiguration::new("order".to_string(), None, Some("created".to_string()))),
+        ];
+        let matcher = EndpointMatcher::new(&endpoints).unwrap();
+        let request = TestRequest::get().uri("/order").to_http_request();
+        let scenarios = ScenarioStates::new();
+        scenarios.add(&[ScenarioConfiguration::new("order".to_string(), "empty".to_string(), vec![])]);
+        assert_eq!(matcher.find(&request, &[], &scenarios), Some(1));
+        scenarios.set_state("order", "created");
+        assert_eq!(matcher.find(&request, &[], &scenarios), Some(0));
+    }
+
+    /**
+     * Verifying that invalid patterns fail.
+     */
+    #[test]
+    fn test_invalid_pattern() {
+        let endpoints = vec![EndpointConfiguration::new("/test(".to_string(), "GET".to_string(), None, None, None)];
+        assert!(EndpointMatcher::new(&endpoints).is_err());
+    }
+}
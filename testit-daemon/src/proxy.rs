@@ -0,0 +1,422 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, CloseReason, Session};
+use futures_util::{stream::{SplitSink, SplitStream}, SinkExt, StreamExt};
+use rand::{rngs::StdRng, Rng};
+use testit_lib::{config::{EndpointConfiguration, Fault, RouteConfiguration, WebSocketRelayConfiguration}, error::ApplicationError};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::{self, client::IntoClientRequest, http::{HeaderName, HeaderValue}, protocol::CloseFrame}, MaybeTlsStream, WebSocketStream};
+
+use crate::balancer::Lease;
+
+/**
+ * The sending side of an upstream WebSocket.
+ */
+type UpstreamSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>;
+
+/**
+ * The receiving side of an upstream WebSocket.
+ */
+type UpstreamStream = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+
+/**
+ * Headers that are only valid for a single connection and must not be forwarded by a proxy.
+ * See https://www.rfc-editor.org/rfc/rfc9110#section-7.6.1
+ */
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/**
+ * Request headers that are replaced when forwarding the request.
+ */
+const SKIPPED_REQUEST_HEADERS: [&str; 5] = [
+    "host",
+    "content-length",
+    "x-forwarded-for",
+    "x-forwarded-host",
+    "x-forwarded-proto",
+];
+
+/**
+ * Create the http client used for upstream requests. Redirects are returned to the caller instead of being followed.
+ *
+ * # Returns
+ * @return The http client.
+ *
+ * # Errors
+ * @return An error if the client could not be created.
+ */
+pub fn create_client() -> Result<reqwest::Client, ApplicationError> {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))
+}
+
+/**
+ * Validate the route policy and the WebSocket relay of the endpoint.
+ *
+ * # Arguments
+ * @param endpoint: The endpoint configuration.
+ *
+ * # Returns
+ * @return Ok if the route policy and the WebSocket relay are valid.
+ *
+ * # Errors
+ * @return An error if the endpoint has a route policy without both a route and a mock response.
+ * @return An error if the WebSocket relay has a fault that cannot be executed on a WebSocket connection.
+ */
+pub fn validate(endpoint: &EndpointConfiguration) -> Result<(), ApplicationError> {
+    if endpoint.route_policy.is_some() && (endpoint.route.is_none() || endpoint.mock_responses().next().is_none()) {
+        return Err(ApplicationError::ConfigurationError(format!("Endpoint {} has a route policy without both a route and a mock response", endpoint.id)));
+    }
+    if let Some(fault @ (Fault::CloseAfterHeaders | Fault::MalformedResponse)) = endpoint.route.as_ref().and_then(|route| route.websocket.as_ref()).and_then(|relay| relay.fault) {
+        return Err(ApplicationError::ConfigurationError(format!("Endpoint {} has the WebSocket relay fault {:?}, which is not supported for relayed messages", endpoint.id, fault)));
+    }
+    Ok(())
+}
+
+/**
+ * Check if the request asks for the mock response with the header.
+ *
+ * # Arguments
+ * @param request: The incoming request.
+ * @param name: The header name.
+ * @param value: The value the header must have, or None if the header only must be present.
+ *
+ * # Returns
+ * @return True if the mock response is requested.
+ */
+pub fn is_mock_requested(request: &HttpRequest, name: &str, value: Option<&str>) -> bool {
+    let mut values = request.headers().get_all(name);
+    match value {
+        Some(value) => values.any(|header_value| header_value.as_bytes() == value.as_bytes()),
+        None => values.next().is_some(),
+    }
+}
+
+/**
+ * Forward the request to the upstream service and stream the response back.
+ *
+ * # Arguments
+ * @param client: The http client used for upstream requests.
+ * @param target: The URL of the upstream target.
+ * @param request: The incoming request.
+ * @param body: The incoming request body.
+ * @param timeout: The maximum time to wait for the upstream response headers, or None to wait until the client times out. The body is not limited.
+ *
+ * # Returns
+ * @return The response from the upstream service.
+ *
+ * # Errors
+ * @return An error if the upstream request could not be created or sent.
+ */
+pub async fn forward_request(client: &reqwest::Client, target: &str, request: &HttpRequest, body: web::Bytes, timeout: Option<Duration>) -> Result<HttpResponse, ApplicationError> {
+    let method = reqwest::Method::from_bytes(request.method().as_str().as_bytes()).map_err(|err| ApplicationError::UpstreamError(err.to_string()))?;
+    let url = upstream_url(target, request);
+    let connection_headers = connection_headers(request.headers().get_all("connection").filter_map(|value| value.to_str().ok()));
+    let mut upstream_request = client.request(method, url);
+    for (name, value) in request.headers().iter() {
+        if is_hop_by_hop(name.as_str(), &connection_headers) || SKIPPED_REQUEST_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        upstream_request = upstream_request.header(name.as_str(), value.as_bytes());
+    }
+    upstream_request = upstream_request.header("x-forwarded-for", forwarded_for(request));
+    upstream_request = upstream_request.header("x-forwarded-host", request.connection_info().host().to_string());
+    upstream_request = upstream_request.header("x-forwarded-proto", request.connection_info().scheme().to_string());
+    let upstream_response = upstream_request.body(body).send();
+    let upstream_response = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, upstream_response).await.map_err(|_| ApplicationError::UpstreamError(format!("Upstream did not respond within {} ms", timeout.as_millis())))?,
+        None => upstream_response.await,
+    }.map_err(|err| ApplicationError::UpstreamError(err.to_string()))?;
+    generate_upstream_response(upstream_response)
+}
+
+/**
+ * Convert the upstream response into a streamed response for the caller.
+ *
+ * # Arguments
+ * @param upstream_response: The response from the upstream service.
+ *
+ * # Returns
+ * @return The response to return to the caller.
+ *
+ * # Errors
+ * @return An error if the upstream status code is invalid.
+ */
+fn generate_upstream_response(upstream_response: reqwest::Response) -> Result<HttpResponse, ApplicationError> {
+    let status = StatusCode::from_u16(upstream_response.status().as_u16()).map_err(|err| ApplicationError::UpstreamError(err.to_string()))?;
+    let connection_headers = connection_headers(upstream_response.headers().get_all("connection").iter().filter_map(|value| value.to_str().ok()));
+    let mut response_builder = HttpResponse::build(status);
+    for (name, value) in upstream_response.headers().iter() {
+        if is_hop_by_hop(name.as_str(), &connection_headers) || name.as_str() == "content-length" {
+            continue;
+        }
+        response_builder.append_header((name.as_str(), value.as_bytes()));
+    }
+    Ok(response_builder.streaming(upstream_response.bytes_stream()))
+}
+
+/**
+ * Create the upstream url. The request path and query are appended to the target URL.
+ *
+ * # Arguments
+ * @param target: The URL of the upstream target.
+ * @param request: The incoming request.
+ *
+ * # Returns
+ * @return The upstream url.
+ */
+fn upstream_url(target: &str, request: &HttpRequest) -> String {
+    let mut url = target.trim_end_matches('/').to_string();
+    url.push_str(request.uri().path());
+    if let Some(query) = request.uri().query() {
+        url.push('?');
+        url.push_str(query);
+    }
+    url
+}
+
+/**
+ * Open the WebSocket of the upstream service, accept the WebSocket upgrade and relay messages in both directions.
+ *
+ * # Arguments
+ * @param route: The route configuration.
+ * @param lease: The lease of the upstream target. It is held until the WebSocket session ends.
+ * @param generator: The random generator of the relay faults of the endpoint.
+ * @param request: The incoming upgrade request.
+ * @param payload: The incoming request payload.
+ *
+ * # Returns
+ * @return The upgrade response.
+ *
+ * # Errors
+ * @return An error if the upstream WebSocket could not be opened.
+ * @return An error if the WebSocket handshake with the caller failed.
+ */
+pub async fn forward_websocket(route: &RouteConfiguration, lease: Lease, generator: Arc<Mutex<StdRng>>, request: &HttpRequest, payload: web::Payload) -> Result<HttpResponse, ApplicationError> {
+    let url = upstream_url(lease.endpoint(), request).replacen("http", "ws", 1);
+    let mut upstream_request = url.into_client_request().map_err(|err| ApplicationError::UpstreamError(err.to_string()))?;
+    let connection_headers = connection_headers(request.headers().get_all("connection").filter_map(|value| value.to_str().ok()));
+    for (name, value) in request.headers().iter() {
+        if is_hop_by_hop(name.as_str(), &connection_headers) || SKIPPED_REQUEST_HEADERS.contains(&name.as_str()) || name.as_str().starts_with("sec-websocket-") {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_str().as_bytes()), HeaderValue::from_bytes(value.as_bytes())) {
+            upstream_request.headers_mut().append(name, value);
+        }
+    }
+    let forwarded_headers = [("x-forwarded-for", forwarded_for(request)), ("x-forwarded-host", request.connection_info().host().to_string()), ("x-forwarded-proto", request.connection_info().scheme().to_string())];
+    for (name, value) in forwarded_headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            upstream_request.headers_mut().insert(name, value);
+        }
+    }
+    let upstream = tokio_tungstenite::connect_async(upstream_request).await;
+    lease.report(upstream.is_ok());
+    let (upstream, _) = upstream.map_err(|err| ApplicationError::UpstreamError(err.to_string()))?;
+    let (response, session, stream) = actix_ws::handle(request, payload).map_err(|err| ApplicationError::RequestError(err.to_string()))?;
+    let relay = route.websocket.clone().unwrap_or(WebSocketRelayConfiguration::new(0));
+    actix_web::rt::spawn(relay_messages(relay, generator, lease, session, stream.aggregate_continuations(), upstream));
+    Ok(response)
+}
+
+/**
+ * Relay messages between the caller and the upstream service until one of them closes the connection. Each direction
+ * is relayed on its own, so the delay of a message does not hold back the messages in the other direction.
+ * A connection reset drops both connections without a close frame, an empty response closes both connections and
+ * a hang stops relaying until the caller leaves.
+ *
+ * # Arguments
+ * @param relay: The delay and fault applied to each message.
+ * @param generator: The random generator of the relay faults.
+ * @param _lease: The lease of the upstream target, held until the relay ends.
+ * @param session: The session used to send messages to the caller.
+ * @param stream: The messages from the caller.
+ * @param upstream: The upstream WebSocket.
+ */
+async fn relay_messages(relay: WebSocketRelayConfiguration, generator: Arc<Mutex<StdRng>>, _lease: Lease, session: Session, mut stream: AggregatedMessageStream, upstream: WebSocketStream<MaybeTlsStream<TcpStream>>) {
+    let (mut upstream_sink, mut upstream_stream) = upstream.split();
+    let fault = tokio::select! {
+        fault = relay_to_upstream(&relay, &generator, &mut stream, &mut upstream_sink, session.clone()) => fault,
+        fault = relay_to_caller(&relay, &generator, &mut upstream_stream, session.clone()) => fault,
+    };
+    match fault {
+        None => {
+            let _ = upstream_sink.close().await;
+        }
+        Some(Fault::Hang) => while let Some(Ok(_)) = stream.recv().await {},
+        Some(Fault::EmptyResponse) => {
+            let _ = session.close(None).await;
+            let _ = upstream_sink.close().await;
+        }
+        // The connection reset drops both connections without a close frame.
+        Some(_) => {}
+    }
+}
+
+/**
+ * Relay the messages from the caller to the upstream service.
+ *
+ * # Arguments
+ * @param relay: The delay and fault applied to each message.
+ * @param generator: The random generator of the relay faults.
+ * @param stream: The messages from the caller.
+ * @param upstream_sink: The sending side of the upstream WebSocket.
+ * @param session: The session used to close the caller connection.
+ *
+ * # Returns
+ * @return The fault that stopped the relay, or None if a connection was closed. The upstream connection is closed by relay_messages.
+ */
+async fn relay_to_upstream(relay: &WebSocketRelayConfiguration, generator: &Mutex<StdRng>, stream: &mut AggregatedMessageStream, upstream_sink: &mut UpstreamSink, session: Session) -> Option<Fault> {
+    loop {
+        let Some(Ok(message)) = stream.recv().await else {
+            return None;
+        };
+        if let Some(fault) = relay_message(relay, generator).await {
+            return Some(fault);
+        }
+        let close = matches!(message, AggregatedMessage::Close(_));
+        if upstream_sink.send(upstream_message(message)).await.is_err() || close {
+            let _ = session.close(None).await;
+            return None;
+        }
+    }
+}
+
+/**
+ * Relay the messages from the upstream service to the caller.
+ *
+ * # Arguments
+ * @param relay: The delay and fault applied to each message.
+ * @param generator: The random generator of the relay faults.
+ * @param upstream_stream: The receiving side of the upstream WebSocket.
+ * @param session: The session used to send messages to the caller.
+ *
+ * # Returns
+ * @return The fault that stopped the relay, or None if a connection was closed. The upstream connection is closed by relay_messages.
+ */
+async fn relay_to_caller(relay: &WebSocketRelayConfiguration, generator: &Mutex<StdRng>, upstream_stream: &mut UpstreamStream, mut session: Session) -> Option<Fault> {
+    loop {
+        let Some(Ok(message)) = upstream_stream.next().await else {
+            let _ = session.close(None).await;
+            return None;
+        };
+        if let Some(fault) = relay_message(relay, generator).await {
+            return Some(fault);
+        }
+        let sent = match message {
+            tungstenite::Message::Text(text) => session.text(text.as_str().to_string()).await,
+            tungstenite::Message::Binary(bytes) => session.binary(bytes).await,
+            tungstenite::Message::Ping(bytes) => session.ping(&bytes).await,
+            tungstenite::Message::Pong(bytes) => session.pong(&bytes).await,
+            tungstenite::Message::Close(frame) => {
+                let reason = frame.map(|frame| CloseReason { code: u16::from(frame.code).into(), description: Some(frame.reason.to_string()) });
+                let _ = session.close(reason).await;
+                return None;
+            }
+            tungstenite::Message::Frame(_) => Ok(()),
+        };
+        if sent.is_err() {
+            return None;
+        }
+    }
+}
+
+/**
+ * Apply the delay to a message and decide if the fault is executed instead of relaying it.
+ *
+ * # Arguments
+ * @param relay: The delay and fault applied to each message.
+ * @param generator: The random generator of the relay faults.
+ *
+ * # Returns
+ * @return The fault to execute, or None if the message should be relayed.
+ */
+async fn relay_message(relay: &WebSocketRelayConfiguration, generator: &Mutex<StdRng>) -> Option<Fault> {
+    tokio::time::sleep(Duration::from_millis(relay.delay)).await;
+    let fault = relay.fault?;
+    let roll = generator.lock().unwrap_or_else(|err| err.into_inner()).gen::<f64>() * 100.0;
+    (roll < relay.fault_percentage).then_some(fault)
+}
+
+/**
+ * Convert a message from the caller to a message for the upstream service.
+ *
+ * # Arguments
+ * @param message: The message from the caller.
+ *
+ * # Returns
+ * @return The upstream message.
+ */
+fn upstream_message(message: AggregatedMessage) -> tungstenite::Message {
+    match message {
+        AggregatedMessage::Text(text) => tungstenite::Message::text(text.to_string()),
+        AggregatedMessage::Binary(bytes) => tungstenite::Message::binary(bytes),
+        AggregatedMessage::Ping(bytes) => tungstenite::Message::Ping(bytes),
+        AggregatedMessage::Pong(bytes) => tungstenite::Message::Pong(bytes),
+        AggregatedMessage::Close(reason) => tungstenite::Message::Close(reason.map(|reason| CloseFrame {
+            code: u16::from(reason.code).into(),
+            reason: reason.description.unwrap_or_default().into(),
+        })),
+    }
+}
+
+/**
+ * Get the header names listed in the connection header. These are hop-by-hop headers as well.
+ *
+ * # Arguments
+ * @param values: The values of the connection headers.
+ *
+ * # Returns
+ * @return The lowercase header names.
+ */
+fn connection_headers<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    values
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/**
+ * Check if the header is a hop-by-hop header.
+ *
+ * # Arguments
+ * @param name: The lowercase header name.
+ * @param connection_headers: The header names listed in the connection header.
+ *
+ * # Returns
+ * @return True if the header must not be forwarded.
+ */
+fn is_hop_by_hop(name: &str, connection_headers: &[String]) -> bool {
+    HOP_BY_HOP_HEADERS.contains(&name) || connection_headers.iter().any(|header| header == name)
+}
+
+/**
+ * Create the x-forwarded-for value by appending the peer address to any existing value.
+ *
+ * # Arguments
+ * @param request: The incoming request.
+ *
+ * # Returns
+ * @return The x-forwarded-for value.
+ */
+fn forwarded_for(request: &HttpRequest) -> String {
+    let peer = request.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    match request.headers().get("x-forwarded-for").and_then(|value| value.to_str().ok()) {
+        Some(existing) => format!("{}, {}", existing, peer),
+        None => peer,
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::HashMap, error::Error, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}, time::Instant};
+
+use actix_web::{body::{BodySize, BoxBody, MessageBody}, http::header::HeaderMap, web::{self, Bytes, BytesMut}, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use testit_lib::{config::{EndpointConfiguration, MatchRule, MockResponseConfiguration, QueryMatcherConfiguration, ResponseSequenceConfiguration, SequenceMode, ServerConfiguration, TestConfiguration}};
+
+/**
+ * Response headers that are not recorded since they are set by the server when the mock response is sent.
+ */
+const SKIPPED_RESPONSE_HEADERS: [&str; 3] = [
+    "content-length",
+    "date",
+    "transfer-encoding",
+];
+
+/**
+ * The largest upstream body that is recorded. Larger responses are relayed, but not recorded, so record mode
+ * does not keep unbounded bodies in memory.
+ */
+const MAX_RECORDED_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/**
+ * The Recorder keeps the upstream responses of routed endpoints so they can be saved as mock endpoints.
+ */
+pub struct Recorder {
+    // The recorded exchanges in the order they were completed.
+    exchanges: Arc<Mutex<Vec<RecordedExchange>>>,
+}
+
+/**
+ * A request to a routed endpoint and the upstream response.
+ */
+struct RecordedExchange {
+    // The ID of the server that received the request.
+    server_id: String,
+    // The HTTP method.
+    method: String,
+    // The request path.
+    path: String,
+    // The decoded query parameters.
+    query: Vec<(String, String)>,
+    // The upstream response with the observed latency as delay.
+    response: MockResponseConfiguration,
+}
+
+/**
+ * An upstream response body that is recorded as it is sent to the caller.
+ */
+struct RecordingBody {
+    // The upstream response body.
+    body: BoxBody,
+    // The body sent so far, or None if the body is larger than the recorded size.
+    buffer: Option<BytesMut>,
+    // The exchange to record when the body is complete. None once it is recorded.
+    exchange: Option<RecordedExchange>,
+    // The recorded exchanges.
+    exchanges: Arc<Mutex<Vec<RecordedExchange>>>,
+}
+
+impl Recorder {
+    /**
+     * Create a new empty recorder.
+     *
+     * # Returns
+     * @return The recorder.
+     */
+    pub fn new() -> Self {
+        Recorder {
+            exchanges: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /**
+     * Record the upstream response as its body is sent to the caller. The exchange is recorded when the body is
+     * complete, and the time until the upstream response arrived is recorded as the delay.
+     *
+     * # Arguments
+     * @param server_id: The ID of the server that received the request.
+     * @param request: The request.
+     * @param response: The upstream response.
+     * @param started: The time the request was forwarded.
+     *
+     * # Returns
+     * @return The response with the recorded body.
+     */
+    pub fn record(&self, server_id: &str, request: &HttpRequest, response: HttpResponse, started: Instant) -> HttpResponse {
+        let latency = started.elapsed().as_millis() as u64;
+        let exchange = RecordedExchange {
+            server_id: server_id.to_string(),
+            method: request.method().to_string(),
+            path: request.uri().path().to_string(),
+            query: web::Query::<Vec<(String, String)>>::from_query(request.query_string()).map(|query| query.into_inner()).unwrap_or_default(),
+            response: MockResponseConfiguration::new(None, response.status().as_u16(), recorded_headers(response.headers()), latency),
+        };
+        let exchanges = self.exchanges.clone();
+        response.map_body(|_, body| {
+            let mut body = RecordingBody { body, buffer: Some(BytesMut::new()), exchange: Some(exchange), exchanges };
+            // Empty bodies are not polled, so they are recorded right away.
+            if matches!(body.body.size(), BodySize::None | BodySize::Sized(0)) {
+                body.finish();
+            }
+            body
+        }).map_into_boxed_body()
+    }
+
+    /**
+     * Create a test configuration with the recorded exchanges as mock endpoints. Requests with the same method,
+     * path and query get one endpoint that returns the recorded responses in order.
+     *
+     * # Arguments
+     * @param test_configuration: The test configuration the exchanges were recorded with.
+     *
+     * # Returns
+     * @return The test configuration with a server for each server that recorded exchanges.
+     */
+    pub fn test_configuration(&self, test_configuration: &TestConfiguration) -> TestConfiguration {
+        let exchanges = self.exchanges.lock().unwrap_or_else(|err| err.into_inner());
+        let servers = test_configuration.servers
+            .iter()
+            .filter_map(|server| {
+                let mut requests: Vec<(&RecordedExchange, Vec<MockResponseConfiguration>)> = Vec::new();
+                for exchange in exchanges.iter().filter(|exchange| exchange.server_id == server.id) {
+                    match requests.iter_mut().find(|(first, _)| first.is_same_request(exchange)) {
+                        Some((_, responses)) => responses.push(exchange.response.clone()),
+                        None => requests.push((exchange, vec![exchange.response.clone()])),
+                    }
+                }
+                if requests.is_empty() {
+                    return None;
+                }
+                let mut endpoints: Vec<EndpointConfiguration> = requests.into_iter().map(|(exchange, responses)| recorded_endpoint(exchange, responses)).collect();
+                endpoints.sort_by_key(|endpoint| std::cmp::Reverse(endpoint.query_matchers.len()));
+                Some(ServerConfiguration::new(server.name.clone(), server.http_port, endpoints, server.https_config.clone()))
+            })
+            .collect();
+        TestConfiguration::new(format!("{} (recorded)", test_configuration.name), format!("Recorded from the test {}", test_configuration.id), servers)
+    }
+}
+
+impl RecordingBody {
+    /**
+     * Add a chunk of the body to the recorded body. The exchange is recorded when the announced size is reached.
+     *
+     * # Arguments
+     * @param chunk: The chunk.
+     */
+    fn append(&mut self, chunk: &Bytes) {
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+        if buffer.len() + chunk.len() > MAX_RECORDED_BODY_SIZE {
+            self.buffer = None;
+            return;
+        }
+        buffer.extend_from_slice(chunk);
+        if self.body.size() == BodySize::Sized(buffer.len() as u64) {
+            self.finish();
+        }
+    }
+
+    /**
+     * Record the exchange with the body sent so far. Bodies larger than the recorded size are not recorded.
+     */
+    fn finish(&mut self) {
+        let (Some(mut exchange), Some(buffer)) = (self.exchange.take(), self.buffer.take()) else {
+            return;
+        };
+        if !buffer.is_empty() {
+            match std::str::from_utf8(&buffer) {
+                Ok(text) => exchange.response.response = Some(text.to_string()),
+                Err(_) => exchange.response = exchange.response.with_response_base64(STANDARD.encode(&buffer)),
+            }
+        }
+        self.exchanges.lock().unwrap_or_else(|err| err.into_inner()).push(exchange);
+    }
+}
+
+impl MessageBody for RecordingBody {
+    type Error = Box<dyn Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.body).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => this.append(chunk),
+            Poll::Ready(None) => this.finish(),
+            // A body that failed is not recorded.
+            Poll::Ready(Some(Err(_))) => this.exchange = None,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl RecordedExchange {
+    /**
+     * Check if the exchanges have the same method, path and query.
+     *
+     * # Arguments
+     * @param other: The other exchange.
+     *
+     * # Returns
+     * @return True if the requests are the same.
+     */
+    fn is_same_request(&self, other: &RecordedExchange) -> bool {
+        self.method == other.method && self.path == other.path && self.query == other.query
+    }
+}
+
+/**
+ * Create a mock endpoint for the recorded request. The path and query parameters must be equal to the recorded request.
+ *
+ * # Arguments
+ * @param exchange: The first exchange with the request.
+ * @param responses: The recorded responses of the request in order.
+ *
+ * # Returns
+ * @return The endpoint configuration.
+ */
+fn recorded_endpoint(exchange: &RecordedExchange, mut responses: Vec<MockResponseConfiguration>) -> EndpointConfiguration {
+    let query_matchers = exchange.query.iter().map(|(name, value)| QueryMatcherConfiguration::new(name.clone(), MatchRule::Equals { value: value.clone() })).collect();
+    let endpoint = EndpointConfiguration::new(format!("^{}$", regex::escape(&exchange.path)), exchange.method.clone(), None, None, None).with_query_matchers(query_matchers);
+    match responses.len() {
+        1 => EndpointConfiguration { mock_response: responses.pop(), ..endpoint },
+        _ => endpoint.with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Sequential, responses)),
+    }
+}
+
+/**
+ * Get the headers to record. Repeated headers are joined with a comma.
+ *
+ * # Arguments
+ * @param headers: The response headers.
+ *
+ * # Returns
+ * @return The header names and values.
+ */
+fn recorded_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    let mut recorded: HashMap<String, String> = HashMap::new();
+    for (name, value) in headers.iter().filter(|(name, _)| !SKIPPED_RESPONSE_HEADERS.contains(&name.as_str())) {
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        recorded
+            .entry(name.to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    recorded
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    /**
+     * Verifying that repeated requests become response sequences and binary bodies are base64 encoded.
+     */
+    #[actix_web::test]
+    async fn test_test_configuration() {
+        let server = ServerConfiguration::new("upstream".to_string(), Some(8080), vec![], None);
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(), vec![server.clone()]);
+        let recorder = Recorder::new();
+        let responses = [
+            ("/items", HttpResponse::Ok().insert_header(("content-type", "application/json")).body("[]")),
+            ("/items?page=2", HttpResponse::Ok().body("[1]")),
+            ("/items", HttpResponse::NotFound().finish()),
+            ("/image", HttpResponse::Ok().body(vec![0xff, 0xfe])),
+            ("/large", HttpResponse::Ok().body(vec![b'x'; MAX_RECORDED_BODY_SIZE + 1])),
+        ];
+        for (url, response) in responses {
+            let request = TestRequest::get().uri(url).to_http_request();
+            let response = recorder.record(&server.id, &request, response, Instant::now());
+            actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        }
+        let recorded = recorder.test_configuration(&test_configuration);
+        let endpoints = &recorded.servers[0].endpoints;
+        assert_eq!(endpoints.len(), 3);
+        assert_eq!(endpoints[0].endpoint, "^/items$");
+        assert_eq!(endpoints[0].query_matchers[0].rule, MatchRule::Equals { value: "2".to_string() });
+        let sequence = endpoints[1].response_sequence.as_ref().unwrap();
+        assert_eq!(sequence.responses.iter().map(|response| response.status).collect::<Vec<u16>>(), vec![200, 404]);
+        assert_eq!(sequence.responses[0].response.as_deref(), Some("[]"));
+        assert_eq!(sequence.responses[0].headers.get("content-type").map(String::as_str), Some("application/json"));
+        assert_eq!(endpoints[2].mock_response.as_ref().unwrap().response_base64.as_deref(), Some("//4="));
+    }
+}
@@ -0,0 +1,130 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use testit_lib::{config::{EndpointConfiguration, ScenarioConfiguration}, error::ApplicationError};
+
+/**
+ * The ScenarioStates struct holds the current state of each scenario in a test. It is shared by all servers in the test.
+ */
+pub struct ScenarioStates {
+    // The scenario configurations by name.
+    scenarios: RwLock<HashMap<String, ScenarioConfiguration>>,
+    // The current state by scenario name.
+    states: RwLock<HashMap<String, String>>,
+}
+
+impl ScenarioStates {
+    /**
+     * Create new empty scenario states.
+     *
+     * # Returns
+     * @return The scenario states.
+     */
+    pub fn new() -> Self {
+        ScenarioStates {
+            scenarios: RwLock::new(HashMap::new()),
+            states: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /**
+     * Add the scenarios in their initial state.
+     *
+     * # Arguments
+     * @param scenarios: The scenario configurations.
+     */
+    pub fn add(&self, scenarios: &[ScenarioConfiguration]) {
+        let mut configurations = self.scenarios.write().unwrap_or_else(|err| err.into_inner());
+        let mut states = self.states.write().unwrap_or_else(|err| err.into_inner());
+        for scenario in scenarios.iter() {
+            configurations.insert(scenario.name.clone(), scenario.clone());
+            states.insert(scenario.name.clone(), scenario.initial_state.clone());
+        }
+    }
+
+    /**
+     * Check if the scenario is in the state.
+     *
+     * # Arguments
+     * @param name: The name of the scenario.
+     * @param state: The state.
+     *
+     * # Returns
+     * @return True if the scenario is in the state.
+     */
+    pub fn is_state(&self, name: &str, state: &str) -> bool {
+        self.states.read().unwrap_or_else(|err| err.into_inner()).get(name).is_some_and(|current| current == state)
+    }
+
+    /**
+     * Move the scenario to a new state.
+     *
+     * # Arguments
+     * @param name: The name of the scenario.
+     * @param state: The new state.
+     */
+    pub fn set_state(&self, name: &str, state: &str) {
+        self.states.write().unwrap_or_else(|err| err.into_inner()).insert(name.to_string(), state.to_string());
+    }
+
+    /**
+     * Validate that the scenario and states used by the endpoint exist.
+     *
+     * # Arguments
+     * @param endpoint: The endpoint configuration.
+     *
+     * # Returns
+     * @return Ok if the endpoint scenario is valid.
+     *
+     * # Errors
+     * @return An error if the scenario does not exist.
+     * @return An error if a state is not one of the scenario states.
+     */
+    pub fn validate(&self, endpoint: &EndpointConfiguration) -> Result<(), ApplicationError> {
+        let Some(step) = &endpoint.scenario else {
+            return Ok(());
+        };
+        let configurations = self.scenarios.read().unwrap_or_else(|err| err.into_inner());
+        let scenario = configurations.get(&step.name).ok_or(ApplicationError::ConfigurationError(format!("Endpoint {} uses unknown scenario {}", endpoint.id, step.name)))?;
+        for state in step.required_state.iter().chain(step.new_state.iter()) {
+            if !scenario.states.is_empty() && !scenario.states.contains(state) {
+                return Err(ApplicationError::ConfigurationError(format!("Endpoint {} uses unknown state {} in scenario {}", endpoint.id, state, step.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use testit_lib::config::ScenarioStepConfiguration;
+
+    use super::*;
+
+    /**
+     * Verifying state changes.
+     */
+    #[test]
+    fn test_states() {
+        let scenario_states = ScenarioStates::new();
+        scenario_states.add(&[ScenarioConfiguration::new("order".to_string(), "empty".to_string(), vec!["empty".to_string(), "created".to_string()])]);
+        assert!(scenario_states.is_state("order", "empty"));
+        scenario_states.set_state("order", "created");
+        assert!(scenario_states.is_state("order", "created"));
+        assert!(!scenario_states.is_state("order", "empty"));
+        assert!(!scenario_states.is_state("unknown", "empty"));
+    }
+
+    /**
+     * Verifying validation of endpoint scenarios.
+     */
+    #[test]
+    fn test_validate() {
+        let scenario_states = ScenarioStates::new();
+        scenario_states.add(&[ScenarioConfiguration::new("order".to_string(), "empty".to_string(), vec!["empty".to_string(), "created".to_string()])]);
+        let endpoint = |name: &str, state: &str| EndpointConfiguration::new("/".to_string(), "GET".to_string(), None, None, None)
+            .with_scenario(ScenarioStepConfiguration::new(name.to_string(), Some(state.to_string()), None));
+        assert!(scenario_states.validate(&endpoint("order", "created")).is_ok());
+        assert!(scenario_states.validate(&endpoint("order", "deleted")).is_err());
+        assert!(scenario_states.validate(&endpoint("unknown", "created")).is_err());
+    }
+}
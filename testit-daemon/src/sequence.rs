@@ -0,0 +1,196 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, Mutex};
+
+use rand::{rngs::StdRng, Rng};
+use testit_lib::{config::{EndpointConfiguration, MockResponseConfiguration, ResponseSequenceConfiguration, SequenceMode}, error::ApplicationError};
+
+use crate::latency::{create_generators, SEQUENCE_SEED_SALT};
+
+/**
+ * The ResponseCounters struct counts the calls to each endpoint of a server. It is used to select the
+ * next response of a response sequence.
+ */
+pub struct ResponseCounters {
+    // The number of calls by endpoint index.
+    counters: Vec<AtomicUsize>,
+    // The random generator of the random response sequences by endpoint index.
+    generators: Vec<Mutex<StdRng>>,
+}
+
+impl ResponseCounters {
+    /**
+     * Create new counters for the endpoints.
+     *
+     * # Arguments
+     * @param endpoints: The endpoint configurations.
+     * @param seed: The seed of the random generators, or None to seed them from entropy.
+     *
+     * # Returns
+     * @return The counters.
+     *
+     * # Errors
+     * @return An error if a response sequence is invalid.
+     */
+    pub fn new(endpoints: &[EndpointConfiguration], seed: Option<u64>) -> Result<Self, ApplicationError> {
+        for endpoint in endpoints.iter() {
+            if let Some(response_sequence) = &endpoint.response_sequence {
+                validate(endpoint, response_sequence)?;
+            }
+        }
+        Ok(ResponseCounters {
+            counters: endpoints.iter().map(|_| AtomicUsize::new(0)).collect(),
+            generators: create_generators(endpoints.len(), seed, SEQUENCE_SEED_SALT),
+        })
+    }
+
+    /**
+     * Select the mock response for the call to the endpoint.
+     *
+     * # Arguments
+     * @param index: The index of the endpoint.
+     * @param endpoint: The endpoint configuration.
+     *
+     * # Returns
+     * @return The mock response, or None if the endpoint has no mock response.
+     */
+    pub fn select<'a>(&self, index: usize, endpoint: &'a EndpointConfiguration) -> Option<&'a MockResponseConfiguration> {
+        let Some(response_sequence) = &endpoint.response_sequence else {
+            return endpoint.mock_response.as_ref();
+        };
+        let call = self.counters[index].fetch_add(1, Ordering::SeqCst);
+        let responses = &response_sequence.responses;
+        match response_sequence.mode {
+            SequenceMode::Sequential => responses.get(call.min(responses.len() - 1)),
+            SequenceMode::Cycle => responses.get(call % responses.len()),
+            SequenceMode::Random => {
+                let weights: Vec<u32> = responses.iter().map(|response| response.weight).collect();
+                select_weighted(&weights, &mut *self.generators[index].lock().unwrap_or_else(|err| err.into_inner())).map(|selected| &responses[selected])
+            }
+        }
+    }
+}
+
+/**
+ * Select a random index using the weights. The chance of an index is its weight divided by the sum of all weights.
+ *
+ * # Arguments
+ * @param weights: The weights.
+ * @param generator: The random generator.
+ *
+ * # Returns
+ * @return The selected index, or None if all weights are zero.
+ */
+pub fn select_weighted(weights: &[u32], generator: &mut impl Rng) -> Option<usize> {
+    let total: u64 = weights.iter().map(|weight| *weight as u64).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut selected = generator.gen_range(0..total);
+    for (index, weight) in weights.iter().enumerate() {
+        if selected < *weight as u64 {
+            return Some(index);
+        }
+        selected -= *weight as u64;
+    }
+    None
+}
+
+/**
+ * Validate the response sequence.
+ *
+ * # Arguments
+ * @param endpoint: The endpoint configuration.
+ * @param response_sequence: The response sequence.
+ *
+ * # Returns
+ * @return Ok if the response sequence is valid.
+ *
+ * # Errors
+ * @return An error if the endpoint has both a mock response and a response sequence.
+ * @return An error if the response sequence is empty.
+ * @return An error if all weights are zero for a random response sequence.
+ */
+fn validate(endpoint: &EndpointConfiguration, response_sequence: &ResponseSequenceConfiguration) -> Result<(), ApplicationError> {
+    if endpoint.mock_response.is_some() {
+        return Err(ApplicationError::ConfigurationError(format!("Endpoint {} has both a mock response and a response sequence", endpoint.id)));
+    }
+    if response_sequence.responses.is_empty() {
+        return Err(ApplicationError::ConfigurationError(format!("Endpoint {} has an empty response sequence", endpoint.id)));
+    }
+    if response_sequence.mode == SequenceMode::Random && response_sequence.responses.iter().all(|response| response.weight == 0) {
+        return Err(ApplicationError::ConfigurationError(format!("Endpoint {} has a random response sequence without weights", endpoint.id)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /**
+     * Verifying the sequential and cycle modes.
+     */
+    #[test]
+    fn test_sequential_cycle() {
+        let endpoints = vec![
+            EndpointConfiguration::new("/sequential".to_string(), "GET".to_string(), None, None, None).with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Sequential, vec![
+                MockResponseConfiguration::new(None, 500, HashMap::new(), 0),
+                MockResponseConfiguration::new(None, 503, HashMap::new(), 0),
+                MockResponseConfiguration::new(None, 200, HashMap::new(), 0),
+            ])),
+            EndpointConfiguration::new("/cycle".to_string(), "GET".to_string(), None, None, None).with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Cycle, vec![
+                MockResponseConfiguration::new(None, 500, HashMap::new(), 0),
+                MockResponseConfiguration::new(None, 200, HashMap::new(), 0),
+            ])),
+        ];
+        let counters = ResponseCounters::new(&endpoints, None).unwrap();
+        let sequential: Vec<u16> = (0..5).map(|_| counters.select(0, &endpoints[0]).unwrap().status).collect();
+        assert_eq!(sequential, vec![500, 503, 200, 200, 200]);
+        let cycle: Vec<u16> = (0..5).map(|_| counters.select(1, &endpoints[1]).unwrap().status).collect();
+        assert_eq!(cycle, vec![500, 200, 500, 200, 500]);
+    }
+
+    /**
+     * Verifying that responses without weight are never selected in random mode.
+     */
+    #[test]
+    fn test_random() {
+        let endpoints = vec![EndpointConfiguration::new("/random".to_string(), "GET".to_string(), None, None, None).with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Random, vec![
+            MockResponseConfiguration::new(None, 500, HashMap::new(), 0).with_weight(0),
+            MockResponseConfiguration::new(None, 200, HashMap::new(), 0),
+        ]))];
+        let counters = ResponseCounters::new(&endpoints, None).unwrap();
+        assert!((0..100).all(|_| counters.select(0, &endpoints[0]).unwrap().status == 200));
+    }
+
+    /**
+     * Verifying that seeded random sequences are reproducible.
+     */
+    #[test]
+    fn test_random_seed() {
+        let endpoints = vec![EndpointConfiguration::new("/random".to_string(), "GET".to_string(), None, None, None).with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Random, vec![
+            MockResponseConfiguration::new(None, 500, HashMap::new(), 0),
+            MockResponseConfiguration::new(None, 200, HashMap::new(), 0),
+        ]))];
+        let first = ResponseCounters::new(&endpoints, Some(42)).unwrap();
+        let second = ResponseCounters::new(&endpoints, Some(42)).unwrap();
+        let first: Vec<u16> = (0..20).map(|_| first.select(0, &endpoints[0]).unwrap().status).collect();
+        let second: Vec<u16> = (0..20).map(|_| second.select(0, &endpoints[0]).unwrap().status).collect();
+        assert_eq!(first, second);
+        assert!(first.contains(&500) && first.contains(&200));
+    }
+
+    /**
+     * Verifying that invalid sequences fail.
+     */
+    #[test]
+    fn test_invalid() {
+        let empty = EndpointConfiguration::new("/cycle".to_string(), "GET".to_string(), None, None, None).with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Cycle, vec![]));
+        assert!(ResponseCounters::new(&[empty], None).is_err());
+        let weightless = EndpointConfiguration::new("/random".to_string(), "GET".to_string(), None, None, None).with_response_sequence(ResponseSequenceConfiguration::new(SequenceMode::Random, vec![
+            MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_weight(0),
+        ]));
+        assert!(ResponseCounters::new(&[weightless], None).is_err());
+    }
+}
@@ -1,9 +1,11 @@
-use std::sync::Arc;
+use std::{path::{Path, PathBuf}, sync::{Arc, Mutex}, time::{Duration, Instant}};
 
-use actix_web::{http::StatusCode, web, App, HttpRequest, HttpResponse, HttpServer};
-use testit_lib::{config::{EndpointConfiguration, HttpsConfiguration, MockResponseConfiguration, ServerConfiguration, TestConfiguration}, error::ApplicationError};
+use actix_web::{http::StatusCode, web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer};
+use testit_lib::{config::{HttpsConfiguration, MockResponseConfiguration, RoutePolicy, ServerConfiguration, TestConfiguration}, error::ApplicationError};
+
+use crate::{balancer::UpstreamBalancer, body::BodyFiles, chaos::{inject_chaos, ChaosInjector}, fault::{self, execute_fault}, journal::{create_entry, RequestJournal}, latency::{create_generators, DelaySampler, RELAY_SEED_SALT}, matcher::EndpointMatcher, proxy::{self, create_client, forward_request, forward_websocket, is_mock_requested}, record::Recorder, scenario::ScenarioStates, sequence::ResponseCounters, streaming::{self, stream_response}, template::{self, TemplateContext}, throttle::{self, read_body, throttle_response}, websocket::{is_websocket_upgrade, start_session, WebSocketMatcher}};
+use rand::rngs::StdRng;
 use tokio::sync::RwLock;
-use regex::Regex;
 use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
 
 /**
@@ -11,15 +13,72 @@ use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
  */
 pub struct ServerSetup {
     servers: Arc<RwLock<Vec<AppServer>>>,
+    // The directory relative response files are resolved from.
+    base_path: PathBuf,
+    // The scenario states shared by all servers.
+    scenarios: Arc<ScenarioStates>,
+    // The request journal shared by all servers.
+    journal: Arc<RequestJournal>,
+    // The recorder of upstream responses shared by all servers. Only set in record mode.
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl ServerSetup {
     pub fn new() -> Self {
         ServerSetup {
-            servers: Arc::new(RwLock::new(vec![]))
+            servers: Arc::new(RwLock::new(vec![])),
+            base_path: PathBuf::new(),
+            scenarios: Arc::new(ScenarioStates::new()),
+            journal: Arc::new(RequestJournal::new(0)),
+            recorder: None,
         }
     }
 
+    /**
+     * Get the request journal shared by all servers.
+     * 
+     * # Returns
+     * @return The request journal.
+     */
+    pub fn journal(&self) -> Arc<RequestJournal> {
+        self.journal.clone()
+    }
+
+    /**
+     * Get the recorder of upstream responses shared by all servers.
+     * 
+     * # Returns
+     * @return The recorder, or None if the servers are not in record mode.
+     */
+    pub fn recorder(&self) -> Option<Arc<Recorder>> {
+        self.recorder.clone()
+    }
+
+    /**
+     * Record the upstream responses of routed endpoints so they can be saved as mock endpoints.
+     * 
+     * # Returns
+     * @return The server setup.
+     */
+    pub fn with_recording(mut self) -> Self {
+        self.recorder = Some(Arc::new(Recorder::new()));
+        self
+    }
+
+    /**
+     * Set the directory relative response files are resolved from. This is normally the directory of the configuration file.
+     * 
+     * # Arguments
+     * @param base_path: The directory.
+     * 
+     * # Returns
+     * @return The server setup.
+     */
+    pub fn with_base_path(mut self, base_path: &Path) -> Self {
+        self.base_path = base_path.to_path_buf();
+        self
+    }
+
     pub async fn setup_test(&mut self, test_configuration: &TestConfiguration) {
         let servers: Vec<AppServer> = test_configuration
             .servers
@@ -27,19 +86,121 @@ impl ServerSetup {
             .map(|server_configuration| AppServer::new(server_configuration.clone()))
             .collect();
         self.servers.write().await.extend(servers);
+        self.scenarios.add(&test_configuration.scenarios);
+        self.journal.set_capacity(test_configuration.journal_size);
     }
 
     pub async fn start_servers(&mut self) -> Result<(), ApplicationError> {
         let mut handles = vec![];
         for server in self.servers.write().await.iter_mut() {            
-            handles.push(server.start_server_http().await?);
-            handles.push(server.start_server_https().await?);
+            let server_state = web::Data::new(ServerState::new(server.server_configuration.clone(), &self.base_path, self.scenarios.clone(), self.journal.clone(), self.recorder.clone())?);
+            handles.push(server.start_server_http(server_state.clone()).await?);
+            handles.push(server.start_server_https(server_state).await?);
         }
         Ok(())
     }
 
 }
 
+/**
+ * The state shared by the http and https server of an AppServer.
+ */
+struct ServerState {
+    // The server configuration.
+    server_configuration: ServerConfiguration,
+    // The compiled endpoint matcher.
+    matcher: EndpointMatcher,
+    // The http client used for routed endpoints.
+    client: reqwest::Client,
+    // The response files.
+    body_files: BodyFiles,
+    // The number of calls to each endpoint. Used for response sequences.
+    counters: ResponseCounters,
+    // The sampler of the response delays.
+    delays: DelaySampler,
+    // The chaos injector of the endpoints.
+    chaos: ChaosInjector,
+    // The upstream target selection of the routed endpoints.
+    balancer: UpstreamBalancer,
+    // The random generators of the WebSocket relay faults by endpoint index.
+    relay_generators: Vec<Arc<Mutex<StdRng>>>,
+    // The WebSocket endpoint matcher.
+    websockets: WebSocketMatcher,
+    // The request journal shared by all servers.
+    journal: Arc<RequestJournal>,
+    // The recorder of upstream responses shared by all servers. Only set in record mode.
+    recorder: Option<Arc<Recorder>>,
+    // The scenario states shared by all servers.
+    scenarios: Arc<ScenarioStates>,
+}
+
+impl ServerState {
+    /**
+     * Create a new server state.
+     *
+     * # Arguments
+     * @param server_configuration: The server configuration.
+     * @param base_path: The directory relative response files are resolved from.
+     * @param scenarios: The scenario states shared by all servers.
+     * @param journal: The request journal shared by all servers.
+     * @param recorder: The recorder of upstream responses shared by all servers.
+     *
+     * # Returns
+     * @return The server state.
+     *
+     * # Errors
+     * @return An error if the endpoints could not be compiled.
+     * @return An error if the WebSocket endpoints could not be compiled.
+     * @return An error if a response template is invalid.
+     * @return An error if a response stream is invalid.
+     * @return An error if a response file could not be read.
+     * @return An error if a response sequence is invalid.
+     * @return An error if a delay distribution is invalid.
+     * @return An error if a chaos configuration is invalid.
+     * @return An error if a throttle configuration is invalid.
+     * @return An error if a route is invalid.
+     * @return An error if a route policy is used without both a route and a mock response.
+     * @return An error if a WebSocket relay fault is not supported.
+     * @return An error if an endpoint uses an unknown scenario or state.
+     * @return An error if the http client could not be created.
+     */
+    fn new(server_configuration: ServerConfiguration, base_path: &Path, scenarios: Arc<ScenarioStates>, journal: Arc<RequestJournal>, recorder: Option<Arc<Recorder>>) -> Result<Self, ApplicationError> {
+        let matcher = EndpointMatcher::new(&server_configuration.endpoints)?;
+        let mut body_files = BodyFiles::new(base_path);
+        for mock_response in server_configuration.endpoints.iter().flat_map(|endpoint| endpoint.mock_responses()).chain(server_configuration.fallback.iter()) {
+            validate_mock_response(mock_response)?;
+            streaming::validate(mock_response)?;
+            body_files.load(mock_response)?;
+        }
+        let counters = ResponseCounters::new(&server_configuration.endpoints, server_configuration.seed)?;
+        let delays = DelaySampler::new(&server_configuration.endpoints, server_configuration.fallback.as_ref(), server_configuration.seed)?;
+        let chaos = ChaosInjector::new(&server_configuration)?;
+        let balancer = UpstreamBalancer::new(&server_configuration.endpoints, server_configuration.seed)?;
+        let relay_generators = create_generators(server_configuration.endpoints.len(), server_configuration.seed, RELAY_SEED_SALT).into_iter().map(Arc::new).collect();
+        let websockets = WebSocketMatcher::new(&server_configuration.websockets)?;
+        for endpoint in server_configuration.endpoints.iter() {
+            scenarios.validate(endpoint)?;
+            throttle::validate(endpoint)?;
+            proxy::validate(endpoint)?;
+        }
+        Ok(ServerState {
+            server_configuration,
+            matcher,
+            client: create_client()?,
+            body_files,
+            counters,
+            delays,
+            chaos,
+            balancer,
+            relay_generators,
+            websockets,
+            journal,
+            recorder,
+            scenarios,
+        })
+    }
+}
+
 struct AppServer {
     server_configuration: ServerConfiguration,
 }
@@ -51,14 +212,13 @@ impl AppServer {
         }
     }
 
-    async fn start_server_http(&mut self) -> Result<(), ApplicationError> {
+    async fn start_server_http(&mut self, server_state: web::Data<ServerState>) -> Result<(), ApplicationError> {
         if let Some(http_port) = self.server_configuration.http_port {
-            let appstate = web::Data::new(self.server_configuration.clone());
             let server = HttpServer::new(move || {
                 App::new()
-                    .app_data(appstate.clone())
+                    .app_data(server_state.clone())
                     .default_service(web::to(request_handler))
-            }).bind(("127.0.0.1", http_port)).map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
+            }).on_connect(fault::on_connect).bind(("127.0.0.1", http_port)).map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
             let server = server.workers(2).run();
             tokio::spawn(async move {
                 match server.await {
@@ -73,22 +233,24 @@ impl AppServer {
     /**
      * Start the server with HTTPS.
      * 
+     * # Arguments
+     * @param server_state: The state shared with the http server.
+     * 
      * # Returns
      * @return Ok if the server was started.
      * 
      * # Errors
      * @return An error if the server could not be started.
      */
-    async fn start_server_https(&self) -> Result<(), ApplicationError> {
+    async fn start_server_https(&self, server_state: web::Data<ServerState>) -> Result<(), ApplicationError> {
         let config = self.server_configuration.clone();
         if let Some(https_config) = config.https_config {                        
             let ssl_builder = ssl_builder(&https_config)?;
-            let appstate = web::Data::new(self.server_configuration.clone());
             let server = HttpServer::new(move || {
                 App::new()
-                    .app_data(appstate.clone())
+                    .app_data(server_state.clone())
                     .default_service(web::to(request_handler))
-            }).bind_openssl("127.0.0.1:".to_owned() + https_config.https_port.to_string().as_str(), ssl_builder).map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
+            }).on_connect(fault::on_connect).bind_openssl("127.0.0.1:".to_owned() + https_config.https_port.to_string().as_str(), ssl_builder).map_err(|err| ApplicationError::ServerStartUpError(err.to_string()))?;
             let server = server.workers(2).run();
             tokio::spawn(async move {
                 match server.await {
@@ -102,73 +264,319 @@ impl AppServer {
     }      
 }
 
+/**
+ * The response to a request and the request data recorded in the journal.
+ */
+struct HandledRequest {
+    // The ID of the matched endpoint or WebSocket endpoint.
+    endpoint_id: Option<String>,
+    // The request body.
+    body: web::Bytes,
+    // The response.
+    response: HttpResponse,
+}
+
+impl HandledRequest {
+    /**
+     * Create a new handled request.
+     * 
+     * # Arguments
+     * @param endpoint_id: The ID of the matched endpoint.
+     * @param body: The request body.
+     * @param response: The response.
+     * 
+     * # Returns
+     * @return The handled request.
+     */
+    fn new(endpoint_id: Option<&str>, body: web::Bytes, response: HttpResponse) -> Self {
+        HandledRequest {
+            endpoint_id: endpoint_id.map(str::to_string),
+            body,
+            response,
+        }
+    }
+}
+
+/**
+ * Handle the request and record it in the journal.
+ * 
+ * # Arguments
+ * @param server_state: The server state.
+ * @param req: The request.
+ * @param payload: The request payload.
+ * 
+ * # Returns
+ * @return The response.
+ */
+async fn request_handler(server_state: web::Data<ServerState>, req: HttpRequest, payload: web::Payload) -> HttpResponse {
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let received = Instant::now();
+    let handled = handle_request(&server_state, &req, payload).await;
+    let (entry, response) = create_entry(timestamp, &server_state.server_configuration.id, handled.endpoint_id.as_deref(), &req, &handled.body, handled.response, received.elapsed());
+    server_state.journal.record(entry);
+    response
+}
+
 /**
  * Handle the request.
  * 
  * # Arguments
- * @param server_configuration: The server configuration.
+ * @param server_state: The server state.
  * @param req: The request.
+ * @param payload: The request payload.
+ * 
+ * # Returns
+ * @return The response and the request data recorded in the journal.
+ */
+async fn handle_request(server_state: &ServerState, req: &HttpRequest, payload: web::Payload) -> HandledRequest {
+    if let Some(script) = server_state.websockets.find(req) {
+        let response = match start_session(script.clone(), req, payload) {
+            Ok(response) => response,
+            Err(err) => {
+                eprintln!("{}", err);
+                HttpResponse::BadRequest().body(err.to_string())
+            }
+        };
+        return HandledRequest::new(Some(script.id()), web::Bytes::new(), response);
+    }
+    if is_websocket_upgrade(req) {
+        let index = server_state.matcher.find(req, &[], &server_state.scenarios);
+        if let Some((index, endpoint, route)) = index.and_then(|index| {
+            let endpoint = &server_state.server_configuration.endpoints[index];
+            Some((index, endpoint, endpoint.route.as_ref()?))
+        }) {
+            if let Some(response) = apply_chaos(server_state, index, req).await {
+                return HandledRequest::new(Some(&endpoint.id), web::Bytes::new(), response);
+            }
+            let Some(lease) = server_state.balancer.select(index) else {
+                return HandledRequest::new(Some(&endpoint.id), web::Bytes::new(), HttpResponse::NotImplemented().body("Not implemented"));
+            };
+            let response = match forward_websocket(route, lease, server_state.relay_generators[index].clone(), req, payload).await {
+                Ok(response) => response,
+                Err(ApplicationError::UpstreamError(err)) => {
+                    eprintln!("Upstream error: {}", err);
+                    HttpResponse::BadGateway().body(err)
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    HttpResponse::BadRequest().body(err.to_string())
+                }
+            };
+            return HandledRequest::new(Some(&endpoint.id), web::Bytes::new(), response);
+        }
+    }
+    let request_throttle = server_state.matcher.find_before_body(req, &server_state.scenarios).and_then(|index| server_state.server_configuration.endpoints[index].request_throttle.as_ref());
+    let body = match read_body(payload, request_throttle).await {
+        Ok(body) => body,
+        Err(err) => {
+            eprintln!("{}", err);
+            return HandledRequest::new(None, web::Bytes::new(), HttpResponse::BadRequest().body(err.to_string()));
+        }
+    };
+    let Some(index) = server_state.matcher.find(req, &body, &server_state.scenarios) else {
+        let response = unmatched_response(server_state, req, &body).await;
+        return HandledRequest::new(None, body, response);
+    };
+    let endpoint = &server_state.server_configuration.endpoints[index];
+    if let Some(response) = apply_chaos(server_state, index, req).await {
+        return HandledRequest::new(Some(&endpoint.id), body, response);
+    }
+    let response = match handle_endpoint(server_state, index, req, body.clone()).await {
+        Ok(response) => {
+            update_scenario(server_state, index);
+            match &endpoint.response_throttle {
+                Some(response_throttle) => throttle_response(response, response_throttle),
+                None => response,
+            }
+        }
+        Err(ApplicationError::UpstreamError(err)) => {
+            eprintln!("Upstream error: {}", err);
+            HttpResponse::BadGateway().body(err)
+        }
+        Err(err) => {   
+            eprintln!("{}", err);    
+            HttpResponse::NotImplemented().body("Not implemented")
+        }
+    };
+    HandledRequest::new(Some(&endpoint.id), body, response)
+}
+
+/**
+ * Create the response to a request that did not match any endpoint. The closest endpoints and the criteria
+ * that failed are logged and returned. If the path matched but the method did not, 405 is returned with
+ * the allowed methods. Otherwise the fallback response is returned with its delay and fault, or 404 if there
+ * is no fallback.
+ * 
+ * # Arguments
+ * @param server_state: The server state.
+ * @param req: The request.
+ * @param body: The request body.
  * 
  * # Returns
  * @return The response.
  */
-async fn request_handler(server_configuration: web::Data<ServerConfiguration>, req: HttpRequest) -> HttpResponse {
-    for endpoint in server_configuration.endpoints.iter() {
-        match is_valid_endpoint(&req, endpoint) {
-            Ok(true) => { 
-                match handle_endpoint(endpoint) {
-                    Ok(response) => return response,
-                    Err(err) => {   
-                        eprintln!("{}", err);    
-                        return HttpResponse::NotImplemented().body("Not implemented"); 
-                    }
-                }                
-            },
-            Ok(false) => continue,
-            Err(err) => return HttpResponse::ServiceUnavailable().body(err.to_string())
-        }    
-    }
-    HttpResponse::NotImplemented().body("Not implemented")
+async fn unmatched_response(server_state: &ServerState, req: &HttpRequest, body: &[u8]) -> HttpResponse {
+    let diagnosis = server_state.matcher.diagnose(req, body, &server_state.scenarios);
+    let message = format!("No endpoint matched {} {} on {}", req.method(), req.uri(), server_state.server_configuration.name);
+    eprintln!("{}", message);
+    for near_miss in diagnosis.near_misses.iter() {
+        eprintln!("  {} {} {} failed: {}", near_miss.endpoint_id, near_miss.method, near_miss.endpoint, near_miss.failed.join(", "));
+    }
+    let diagnostic = serde_json::json!({ "message": message, "nearMisses": diagnosis.near_misses });
+    if !diagnosis.allowed_methods.is_empty() {
+        return HttpResponse::MethodNotAllowed().insert_header(("allow", diagnosis.allowed_methods.join(", "))).json(diagnostic);
+    }
+    if let Some(fallback) = &server_state.server_configuration.fallback {
+        let context = fallback.template.then(|| TemplateContext::new(req, body, None));
+        match respond_with_mock(server_state, fallback, server_state.delays.fallback_delay(fallback), req, context.as_ref()).await {
+            Ok(response) => return response,
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    HttpResponse::NotFound().json(diagnostic)
 }
 
+/**
+ * Move the scenario of the endpoint to its new state.
+ * 
+ * # Arguments
+ * @param server_state: The server state.
+ * @param index: The index of the endpoint that responded.
+ */
+fn update_scenario(server_state: &ServerState, index: usize) {
+    let endpoint = &server_state.server_configuration.endpoints[index];
+    if let Some(scenario) = &endpoint.scenario {
+        if let Some(new_state) = &scenario.new_state {
+            server_state.scenarios.set_state(&scenario.name, new_state);
+        }
+    }
+}
 
+/**
+ * Inject the chaos of the endpoint into the request, if the endpoint is selected for chaos.
+ * 
+ * # Arguments
+ * @param server_state: The server state.
+ * @param index: The index of the matched endpoint.
+ * @param request: The request.
+ * 
+ * # Returns
+ * @return The response that replaces the endpoint response, or None if the endpoint should respond.
+ */
+async fn apply_chaos(server_state: &ServerState, index: usize, request: &HttpRequest) -> Option<HttpResponse> {
+    let chaos = server_state.chaos.select(index)?;
+    match inject_chaos(chaos, request).await {
+        Ok(response) => response,
+        Err(err) => {
+            eprintln!("{}", err);
+            Some(HttpResponse::NotImplemented().body("Not implemented"))
+        }
+    }
+}
+
+/**
+ * Handle the endpoint. The route policy decides if the request is forwarded to the route or the mock response is used.
+ * 
+ * # Arguments
+ * @param server_state: The server state.
+ * @param index: The index of the matched endpoint.
+ * @param request: The request.
+ * @param body: The request body.
+ * 
+ * # Returns
+ * @return The response.
+ * 
+ * # Errors
+ * @return An error if the status code is invalid.
+ * @return An error if the response template could not be rendered.
+ * @return An error if the request could not be forwarded to the route.
+ * @return An error if the fault could not be executed.
+ */
+async fn handle_endpoint(server_state: &ServerState, index: usize, request: &HttpRequest, body: web::Bytes) -> Result<HttpResponse, ApplicationError> {
+    let endpoint = &server_state.server_configuration.endpoints[index];
+    match (&endpoint.route, &endpoint.route_policy) {
+        (Some(_), Some(RoutePolicy::MockOnFailure { timeout })) => {
+            match forward_to_route(server_state, index, request, body.clone(), timeout.map(Duration::from_millis), true).await {
+                Ok(response) => return Ok(response),
+                Err(err) => eprintln!("{} for {}, using the mock response", err, endpoint.id),
+            }
+        }
+        (Some(_), Some(RoutePolicy::MockOnHeader { name, value })) if !is_mock_requested(request, name, value.as_deref()) => {
+            return forward_to_route(server_state, index, request, body, None, false).await;
+        }
+        _ => {}
+    }
+    if let Some(mock_response) = server_state.counters.select(index, endpoint) {
+        let context = mock_response.template.then(|| TemplateContext::new(request, &body, server_state.matcher.captures(index, request.path())));
+        return respond_with_mock(server_state, mock_response, server_state.delays.delay(index, mock_response), request, context.as_ref()).await;
+    }
+    if endpoint.route.is_some() {
+        return forward_to_route(server_state, index, request, body, None, false).await;
+    }
+    Ok(HttpResponse::NotImplemented().body("Not implemented"))
+}
 
 /**
- * Check if the request is a valid endpoint.
+ * Forward the request to an upstream target of the route. The outcome is reported to the balancer, where a connection
+ * error, a timeout or a server error counts as a failure. The upstream response is recorded in record mode when it
+ * is returned.
  * 
  * # Arguments
+ * @param server_state: The server state.
+ * @param index: The index of the routed endpoint.
  * @param request: The request.
- * @param endpoint: The endpoint configuration.
+ * @param body: The request body.
+ * @param timeout: The maximum time to wait for the upstream response.
+ * @param fail_on_server_error: True if a server error is returned as an error instead of the upstream response.
  * 
  * # Returns
- * @return True if the request is a valid endpoint.
+ * @return The upstream response.
  * 
  * # Errors
- * @return An error if the endpoint is invalid.
+ * @return An error if the request could not be forwarded to the route.
+ * @return An error if the upstream returned a server error and fail_on_server_error is set.
  */
-fn is_valid_endpoint(request: &HttpRequest, endpoint: &EndpointConfiguration) -> Result<bool, ApplicationError> {
-    let regexp = Regex::new(&endpoint.endpoint).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?;
-    Ok(regexp.is_match(request.uri().path()) && request.method().as_str() == endpoint.method.as_str())
+async fn forward_to_route(server_state: &ServerState, index: usize, request: &HttpRequest, body: web::Bytes, timeout: Option<Duration>, fail_on_server_error: bool) -> Result<HttpResponse, ApplicationError> {
+    let endpoint = &server_state.server_configuration.endpoints[index];
+    let lease = server_state.balancer.select(index).ok_or_else(|| ApplicationError::UpstreamError(format!("Endpoint {} has no route", endpoint.id)))?;
+    let started = Instant::now();
+    let response = forward_request(&server_state.client, lease.endpoint(), request, body, timeout).await;
+    lease.report(response.as_ref().is_ok_and(|response| !response.status().is_server_error()));
+    let response = response?;
+    if fail_on_server_error && response.status().is_server_error() {
+        return Err(ApplicationError::UpstreamError(format!("Upstream returned {}", response.status())));
+    }
+    let response = match &server_state.recorder {
+        Some(recorder) => recorder.record(&server_state.server_configuration.id, request, response, started),
+        None => response,
+    };
+    Ok(lease.attach(response))
 }
 
 /**
- * Handle the endpoint.
+ * Respond with the mock response after its delay. The fault replaces the response if it is set.
  * 
  * # Arguments
- * @param endpoint: The endpoint configuration.
+ * @param server_state: The server state.
+ * @param mock_response: The mock response configuration.
+ * @param delay: The delay of the mock response.
+ * @param request: The request.
+ * @param context: The request data used when the response is a template.
  * 
  * # Returns
  * @return The response.
  * 
  * # Errors
  * @return An error if the status code is invalid.
+ * @return An error if the response template could not be rendered.
+ * @return An error if the fault could not be executed.
  */
-fn handle_endpoint(endpoint: &EndpointConfiguration) -> Result<HttpResponse, ApplicationError> {
-    if let Some(mock_response) = &endpoint.mock_response {
-        std::thread::sleep(std::time::Duration::from_millis(mock_response.delay));
-        return generate_mock_response(mock_response);
-    } 
-    Ok(HttpResponse::NotImplemented().body("Not implemented"))
+async fn respond_with_mock(server_state: &ServerState, mock_response: &MockResponseConfiguration, delay: Duration, request: &HttpRequest, context: Option<&TemplateContext<'_>>) -> Result<HttpResponse, ApplicationError> {
+    tokio::time::sleep(delay).await;
+    if let Some(fault) = mock_response.fault {
+        return execute_fault(fault, request, fault_response_builder(mock_response)?).await;
+    }
+    generate_mock_response(mock_response, &server_state.body_files, context)
 }
 
 /**
@@ -176,24 +584,105 @@ fn handle_endpoint(endpoint: &EndpointConfiguration) -> Result<HttpResponse, App
  * 
  * # Arguments
  * @param mock_response: The mock response configuration.
+ * @param body_files: The response files.
+ * @param context: The request data used when the response is a template.
  * 
  * # Returns
  * @return The generated response.
  * 
  * # Errors
  * @return An error if the status code is invalid.
+ * @return An error if the response template could not be rendered.
+ * @return An error if the response file or base64 body could not be read.
  */
-fn generate_mock_response(mock_response: &MockResponseConfiguration) -> Result<HttpResponse, ApplicationError> {
-    let mut response_builder: actix_web::HttpResponseBuilder = HttpResponse::build(StatusCode::from_u16(mock_response.status).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?);
+fn generate_mock_response(mock_response: &MockResponseConfiguration, body_files: &BodyFiles, context: Option<&TemplateContext>) -> Result<HttpResponse, ApplicationError> {
+    let mut response_builder = HttpResponse::build(status_code(mock_response)?);
     for (key, value) in mock_response.headers.iter() {
-        response_builder.append_header((key.as_str(), value.as_str()));
+        match context {
+            Some(context) => response_builder.append_header((key.as_str(), template::render(value, context)?)),
+            None => response_builder.append_header((key.as_str(), value.as_str())),
+        };
+    }
+    if let Some(stream) = &mock_response.stream {
+        return stream_response(response_builder, mock_response, stream, context);
     }
     if let Some(response) = &mock_response.response {
-        return Ok(response_builder.body(response.clone()));
+        return match context {
+            Some(context) => Ok(response_builder.body(template::render(response, context)?)),
+            None => Ok(response_builder.body(response.clone())),
+        };
+    }
+    if let Some(mock_body) = body_files.get(mock_response)? {
+        let has_content_type = mock_response.headers.keys().any(|key| key.eq_ignore_ascii_case("content-type"));
+        if let (Some(content_type), false) = (mock_body.content_type, has_content_type) {
+            response_builder.insert_header(("content-type", content_type));
+        }
+        return Ok(response_builder.body(mock_body.bytes));
     }
     Ok(response_builder.finish())
 }
 
+/**
+ * Create the response builder used by faults that send the status and headers.
+ * 
+ * # Arguments
+ * @param mock_response: The mock response configuration.
+ * 
+ * # Returns
+ * @return The response builder with the configured status and headers.
+ * 
+ * # Errors
+ * @return An error if the status code is invalid.
+ */
+fn fault_response_builder(mock_response: &MockResponseConfiguration) -> Result<HttpResponseBuilder, ApplicationError> {
+    let mut response_builder = HttpResponse::build(status_code(mock_response)?);
+    for (key, value) in mock_response.headers.iter() {
+        response_builder.append_header((key.as_str(), value.as_str()));
+    }
+    Ok(response_builder)
+}
+
+/**
+ * Get the status code of the mock response.
+ * 
+ * # Arguments
+ * @param mock_response: The mock response configuration.
+ * 
+ * # Returns
+ * @return The status code.
+ * 
+ * # Errors
+ * @return An error if the status code is invalid.
+ */
+fn status_code(mock_response: &MockResponseConfiguration) -> Result<StatusCode, ApplicationError> {
+    StatusCode::from_u16(mock_response.status).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))
+}
+
+/**
+ * Validate the mock response configuration.
+ * 
+ * # Arguments
+ * @param mock_response: The mock response configuration.
+ * 
+ * # Returns
+ * @return Ok if the mock response is valid.
+ * 
+ * # Errors
+ * @return An error if the response or a header value is an invalid template.
+ */
+fn validate_mock_response(mock_response: &MockResponseConfiguration) -> Result<(), ApplicationError> {
+    if !mock_response.template {
+        return Ok(());
+    }
+    for value in mock_response.headers.values() {
+        template::validate(value)?;
+    }
+    if let Some(response) = &mock_response.response {
+        template::validate(response)?;
+    }
+    Ok(())
+}
+
 /**
  * Create a new SSL builder.
  * 
@@ -218,7 +707,9 @@ fn ssl_builder(https_config: &HttpsConfiguration) -> Result<SslAcceptorBuilder,
 
 #[cfg(test)]
 mod test {
-    use std::{collections::HashMap, fs::File, io::Read, thread, time::Duration};
+    use std::{collections::HashMap, fs::File, io::{Read, Write}, thread, time::Duration};
+
+    use testit_lib::config::{ChaosConfiguration, EjectionConfiguration, EndpointConfiguration, Fault, HeaderMatcherConfiguration, LoadBalancingStrategy, MatchRule, RouteConfiguration, ScenarioConfiguration, ScenarioStepConfiguration, ServerSentEventConfiguration, StreamConfiguration, ThrottleConfiguration, UpstreamTargetConfiguration, WebSocketCloseConfiguration, WebSocketConfiguration, WebSocketMessageConfiguration, WebSocketRelayConfiguration, WebSocketReplyConfiguration};
 
     use super::*;
 
@@ -236,6 +727,10 @@ mod test {
                     id: "test".to_string(),
                     endpoints: vec![],
                     https_config: None,
+                    seed: None,
+                    chaos: None,
+                    websockets: vec![],
+                    fallback: None,
                     
                 },
                 ServerConfiguration {
@@ -244,11 +739,17 @@ mod test {
                     id: "test".to_string(),
                     endpoints: vec![],
                     https_config: None,
+                    seed: None,
+                    chaos: None,
+                    websockets: vec![],
+                    fallback: None,
                 },
             ],
             name: "test".to_string(),
             description: "test".to_string(),
             id: "test".to_string(),
+            scenarios: vec![],
+            journal_size: 1000,
         };
         let mut server_setup = ServerSetup::new();
         server_setup.setup_test(&test_configuration).await;
@@ -256,9 +757,9 @@ mod test {
         assert!(result.is_ok());
         thread::sleep(Duration::from_secs(1));
         let res = reqwest::get("http://localhost:8080").await.unwrap();
-        assert_eq!(res.status(), 501);
+        assert_eq!(res.status(), 404);
         let res = reqwest::get("http://localhost:8081").await.unwrap();
-        assert_eq!(res.status(), 501);          
+        assert_eq!(res.status(), 404);          
     }
 
     /**
@@ -283,7 +784,7 @@ mod test {
         assert_eq!(res.status(), 200);
         assert_eq!(res.text().await.unwrap(), "{}".to_string());
         let res = reqwest::get("http://localhost:8082").await.unwrap();
-        assert_eq!(res.status(), 501);              
+        assert_eq!(res.status(), 404);              
     }   
 
     /**
@@ -318,4 +819,556 @@ mod test {
         assert_eq!(res.status(), 200);                  
     }
 
+    /**
+     * Echo handler used as upstream service in the route tests.
+     */
+    async fn echo_handler(req: HttpRequest, body: web::Bytes) -> HttpResponse {
+        let forwarded_for = req.headers().get("x-forwarded-for").map(|value| value.to_str().unwrap().to_string()).unwrap_or_default();
+        let test_header = req.headers().get("x-test").map(|value| value.to_str().unwrap().to_string()).unwrap_or_default();
+        HttpResponse::Created()
+            .append_header(("x-upstream", "echo"))
+            .body(format!("{} {} {} {} {} {}", req.method(), req.uri().path(), req.uri().query().unwrap_or_default(), test_header, forwarded_for, String::from_utf8_lossy(&body)))
+    }
+
+    /**
+     * Verifying that requests are forwarded to the route.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_route() {
+        let upstream = HttpServer::new(|| App::new().default_service(web::to(echo_handler))).bind(("127.0.0.1", 8085)).unwrap().run();
+        tokio::spawn(upstream);
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8086), vec![
+                EndpointConfiguration::new("/route".to_string(), "POST".to_string(), None, None, Some(RouteConfiguration::new("http://127.0.0.1:8085/".to_string()))),
+                EndpointConfiguration::new("/unavailable".to_string(), "GET".to_string(), None, None, Some(RouteConfiguration::new("http://127.0.0.1:1".to_string()))),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        let res = client.post("http://localhost:8086/route/1?a=b").header("x-test", "value").header("connection", "keep-alive, x-test").body("hello").send().await.unwrap();
+        assert_eq!(res.status(), 201);
+        assert_eq!(res.headers().get("x-upstream").unwrap(), "echo");
+        assert_eq!(res.text().await.unwrap(), "POST /route/1 a=b  127.0.0.1 hello".to_string());
+        let res = client.post("http://localhost:8086/route").header("x-test", "value").body("hello").send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "POST /route  value 127.0.0.1 hello".to_string());
+        let res = reqwest::get("http://localhost:8086/unavailable").await.unwrap();
+        assert_eq!(res.status(), 502);
+    }
+
+    /**
+     * Verifying that delayed responses do not block other requests.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_concurrent_delay() {
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8087), vec![
+                EndpointConfiguration::new("/slow".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("{}".to_string()), 200, HashMap::new(), 1000)), None),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        let start = std::time::Instant::now();
+        let requests: Vec<_> = (0..50).map(|_| tokio::spawn(client.get("http://localhost:8087/slow").send())).collect();
+        for request in requests {
+            assert_eq!(request.await.unwrap().unwrap().status(), 200);
+        }
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    /**
+     * Verifying that scenario states are shared by all servers in the test.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_scenario() {
+        let scenario = |required_state: Option<&str>, new_state: Option<&str>| ScenarioStepConfiguration::new("order".to_string(), required_state.map(str::to_string), new_state.map(str::to_string));
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("orders".to_string(), Some(8088), vec![
+                EndpointConfiguration::new("^/order$".to_string(), "POST".to_string(), None, Some(MockResponseConfiguration::new(None, 201, HashMap::new(), 0)), None).with_scenario(scenario(None, Some("created"))),
+                EndpointConfiguration::new("^/order$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 0)), None).with_scenario(scenario(Some("created"), None)),
+                EndpointConfiguration::new("^/order$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 404, HashMap::new(), 0)), None),
+            ],
+            None),
+            ServerConfiguration::new("admin".to_string(), Some(8089), vec![
+                EndpointConfiguration::new("^/order$".to_string(), "DELETE".to_string(), None, Some(MockResponseConfiguration::new(None, 204, HashMap::new(), 0)), None).with_scenario(scenario(Some("created"), Some("deleted"))),
+            ],
+            None),
+        ]).with_scenarios(vec![ScenarioConfiguration::new("order".to_string(), "empty".to_string(), vec!["empty".to_string(), "created".to_string(), "deleted".to_string()])]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        assert_eq!(client.get("http://localhost:8088/order").send().await.unwrap().status(), 404);
+        assert_eq!(client.post("http://localhost:8088/order").send().await.unwrap().status(), 201);
+        assert_eq!(client.get("http://localhost:8088/order").send().await.unwrap().status(), 200);
+        assert_eq!(client.delete("http://localhost:8089/order").send().await.unwrap().status(), 204);
+        assert_eq!(client.get("http://localhost:8088/order").send().await.unwrap().status(), 404);
+        assert_eq!(client.delete("http://localhost:8089/order").send().await.unwrap().status(), 404);
+    }
+
+    /**
+     * Verifying the connection faults.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_fault() {
+        let fault = |path: &str, fault: Fault| EndpointConfiguration::new(path.to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("{}".to_string()), 200, HashMap::new(), 0).with_fault(fault)), None);
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8090), vec![
+                fault("/reset", Fault::ConnectionReset),
+                fault("/empty", Fault::EmptyResponse),
+                fault("/malformed", Fault::MalformedResponse),
+                fault("/headers", Fault::CloseAfterHeaders),
+                fault("/hang", Fault::Hang),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let raw_request = |path: &str| {
+            let mut stream = std::net::TcpStream::connect("127.0.0.1:8090").unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).unwrap();
+            let mut response = vec![];
+            let result = stream.read_to_end(&mut response);
+            (result.map_err(|err| err.kind()), response)
+        };
+        assert_eq!(raw_request("/reset").0, Err(std::io::ErrorKind::ConnectionReset));
+        assert_eq!(raw_request("/empty"), (Ok(0), vec![]));
+        let (result, response) = raw_request("/malformed");
+        assert!(result.is_ok());
+        assert_eq!(response.len(), 64);
+        let client = reqwest::Client::new();
+        let res = client.get("http://localhost:8090/headers").send().await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert!(res.bytes().await.is_err());
+        let res = client.get("http://localhost:8090/hang").timeout(Duration::from_millis(500)).send().await;
+        assert!(res.unwrap_err().is_timeout());
+    }
+
+    /**
+     * Verifying that chaos is applied to mocked and routed endpoints.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_chaos() {
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8091), vec![
+                EndpointConfiguration::new("/mock".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("{}".to_string()), 200, HashMap::new(), 0)), None),
+                EndpointConfiguration::new("/route".to_string(), "GET".to_string(), None, None, Some(RouteConfiguration::new("http://127.0.0.1:8082".to_string()))),
+                EndpointConfiguration::new("/slow".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("{}".to_string()), 200, HashMap::new(), 0)), None)
+                    .with_chaos(ChaosConfiguration::new(100.0).with_delay(500)),
+            ],
+            None).with_chaos(ChaosConfiguration::new(100.0).with_status(503)),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        assert_eq!(client.get("http://localhost:8091/mock").send().await.unwrap().status(), 503);
+        assert_eq!(client.get("http://localhost:8091/route").send().await.unwrap().status(), 503);
+        let start = std::time::Instant::now();
+        assert_eq!(client.get("http://localhost:8091/slow").send().await.unwrap().status(), 200);
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    }
+
+    /**
+     * Verifying that Server-Sent Events are streamed with their delays.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_server_sent_events() {
+        let events = vec![
+            ServerSentEventConfiguration::new(Some("price".to_string()), "{{request.query.symbol}} 1".to_string(), 0),
+            ServerSentEventConfiguration::new(Some("price".to_string()), "{{request.query.symbol}} 2".to_string(), 300),
+        ];
+        let mock_response = MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_template(true).with_stream(StreamConfiguration::ServerSentEvents { events });
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8093), vec![
+                EndpointConfiguration::new("/prices".to_string(), "GET".to_string(), None, Some(mock_response), None),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let mut res = reqwest::get("http://localhost:8093/prices?symbol=ACME").await.unwrap();
+        assert_eq!(res.headers().get("content-type").unwrap(), "text/event-stream");
+        assert_eq!(res.headers().get("transfer-encoding").unwrap(), "chunked");
+        let start = std::time::Instant::now();
+        assert_eq!(res.chunk().await.unwrap().unwrap(), "event: price\ndata: ACME 1\n\n");
+        assert!(start.elapsed() < Duration::from_millis(300));
+        assert_eq!(res.chunk().await.unwrap().unwrap(), "event: price\ndata: ACME 2\n\n");
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        assert!(res.chunk().await.unwrap().is_none());
+    }
+
+    /**
+     * Verifying a scripted WebSocket conversation and that the close is not held up by a reply delay.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_websocket() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let websocket = WebSocketConfiguration::new("^/ws$".to_string(),
+            vec![WebSocketMessageConfiguration::new("welcome".to_string(), 0)],
+            vec![WebSocketReplyConfiguration::new("^subscribe (.*)$".to_string(), vec![WebSocketMessageConfiguration::new("subscribed".to_string(), 0), WebSocketMessageConfiguration::new("update".to_string(), 100)]),
+                WebSocketReplyConfiguration::new("^slow$".to_string(), vec![WebSocketMessageConfiguration::new("late".to_string(), 5000)])],
+            Some(WebSocketCloseConfiguration::new(4000, Some("done".to_string()), 1000)));
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8094), vec![
+                EndpointConfiguration::new("^/ws$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("http".to_string()), 200, HashMap::new(), 0)), None),
+            ],
+            None).with_websockets(vec![websocket]),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        assert_eq!(reqwest::get("http://localhost:8094/ws").await.unwrap().text().await.unwrap(), "http");
+        let (mut client, _) = tokio_tungstenite::connect_async("ws://localhost:8094/ws").await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::text("welcome"));
+        client.send(Message::text("hello")).await.unwrap();
+        client.send(Message::text("subscribe prices")).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::text("subscribed"));
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::text("update"));
+        client.send(Message::text("slow")).await.unwrap();
+        let start = std::time::Instant::now();
+        let Message::Close(Some(close)) = client.next().await.unwrap().unwrap() else {
+            panic!("Expected close");
+        };
+        assert_eq!(u16::from(close.code), 4000);
+        assert_eq!(close.reason.as_str(), "done");
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /**
+     * Verifying that WebSocket messages are relayed to the route with the delay and fault, and that chaos applies to the handshake.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_websocket_route() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let websocket = WebSocketConfiguration::new("^/ws.*$".to_string(), vec![],
+            vec![WebSocketReplyConfiguration::new("^ping$".to_string(), vec![WebSocketMessageConfiguration::new("pong".to_string(), 0)])], None);
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("upstream".to_string(), Some(8095), vec![], None).with_websockets(vec![websocket]),
+            ServerConfiguration::new("gateway".to_string(), Some(8096), vec![
+                EndpointConfiguration::new("^/ws/slow$".to_string(), "GET".to_string(), None, None,
+                    Some(RouteConfiguration::new("http://127.0.0.1:8095".to_string()).with_websocket(WebSocketRelayConfiguration::new(200)))),
+                EndpointConfiguration::new("^/ws/reset$".to_string(), "GET".to_string(), None, None,
+                    Some(RouteConfiguration::new("http://127.0.0.1:8095".to_string()).with_websocket(WebSocketRelayConfiguration::new(0).with_fault(Fault::ConnectionReset, 100.0)))),
+                EndpointConfiguration::new("^/ws/unavailable$".to_string(), "GET".to_string(), None, None, Some(RouteConfiguration::new("http://127.0.0.1:1".to_string()))),
+                EndpointConfiguration::new("^/ws/chaos$".to_string(), "GET".to_string(), None, None, Some(RouteConfiguration::new("http://127.0.0.1:8095".to_string())))
+                    .with_chaos(ChaosConfiguration::new(100.0).with_status(503)),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let (mut client, _) = tokio_tungstenite::connect_async("ws://localhost:8096/ws/slow").await.unwrap();
+        let start = std::time::Instant::now();
+        client.send(Message::text("ping")).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::text("pong"));
+        assert!(start.elapsed() >= Duration::from_millis(400));
+        let (mut client, _) = tokio_tungstenite::connect_async("ws://localhost:8096/ws/reset").await.unwrap();
+        client.send(Message::text("ping")).await.unwrap();
+        assert!(client.next().await.is_none_or(|message| message.is_err()));
+        assert!(tokio_tungstenite::connect_async("ws://localhost:8096/ws/unavailable").await.is_err());
+        let Err(tokio_tungstenite::tungstenite::Error::Http(response)) = tokio_tungstenite::connect_async("ws://localhost:8096/ws/chaos").await else {
+            panic!("Expected the chaos status");
+        };
+        assert_eq!(response.status(), 503);
+        let invalid = ServerConfiguration::new("invalid".to_string(), None, vec![
+            EndpointConfiguration::new("^/ws$".to_string(), "GET".to_string(), None, None,
+                Some(RouteConfiguration::new("http://127.0.0.1:8095".to_string()).with_websocket(WebSocketRelayConfiguration::new(0).with_fault(Fault::MalformedResponse, 100.0)))),
+        ], None);
+        assert!(ServerState::new(invalid, Path::new(""), Arc::new(ScenarioStates::new()), Arc::new(RequestJournal::new(0)), None).is_err());
+    }
+
+    /**
+     * Verifying throttled request and response bodies.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_throttle() {
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8092), vec![
+                EndpointConfiguration::new("/download".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("x".repeat(50)), 200, HashMap::new(), 0)), None)
+                    .with_response_throttle(ThrottleConfiguration::BytesPerSecond { bytes_per_second: 100 }),
+                EndpointConfiguration::new("/upload".to_string(), "POST".to_string(), None, Some(MockResponseConfiguration::new(None, 202, HashMap::new(), 0)), None)
+                    .with_header_matchers(vec![HeaderMatcherConfiguration::new("x-fast".to_string(), MatchRule::Present)]),
+                EndpointConfiguration::new("/upload".to_string(), "POST".to_string(), None, Some(MockResponseConfiguration::new(None, 204, HashMap::new(), 0)), None)
+                    .with_request_throttle(ThrottleConfiguration::Chunks { chunk_size: 100, pause: 50 }),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        let start = std::time::Instant::now();
+        let res = client.get("http://localhost:8092/download").send().await.unwrap();
+        assert_eq!(res.content_length(), Some(50));
+        assert_eq!(res.text().await.unwrap(), "x".repeat(50));
+        assert!(start.elapsed() >= Duration::from_millis(400));
+        let start = std::time::Instant::now();
+        let res = client.post("http://localhost:8092/upload").body("x".repeat(1000)).send().await.unwrap();
+        assert_eq!(res.status(), 204);
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        let start = std::time::Instant::now();
+        let res = client.post("http://localhost:8092/upload").header("x-fast", "true").body("x".repeat(1000)).send().await.unwrap();
+        assert_eq!(res.status(), 202);
+        assert!(start.elapsed() < Duration::from_millis(400));
+    }
+
+    /**
+     * Verifying that matched and unmatched requests are recorded in the journal and returned by the admin server.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_journal() {
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("test".to_string(), Some(8097), vec![
+                EndpointConfiguration::new("^/orders$".to_string(), "POST".to_string(), None, Some(MockResponseConfiguration::new(Some("{}".to_string()), 201, HashMap::new(), 0)), None),
+            ],
+            None),
+        ]).with_journal_size(2);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        crate::admin::start_admin_server(8098, server_setup.journal()).await.unwrap();
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        client.get("http://localhost:8097/first").send().await.unwrap();
+        client.post("http://localhost:8097/orders?id=1").header("x-test", "value").body("{\"id\": 1}").send().await.unwrap();
+        client.get("http://localhost:8097/missing").send().await.unwrap();
+        let entries: Vec<testit_lib::journal::JournalEntry> = serde_json::from_str(client.get("http://localhost:8098/__admin/requests").send().await.unwrap().text().await.unwrap().as_str()).unwrap();
+        assert_eq!(entries.len(), 2);
+        let order = &entries[0];
+        assert_eq!(order.server_id, test_configuration.servers[0].id);
+        assert_eq!(order.endpoint_id, test_configuration.servers[0].endpoints[0].id);
+        assert_eq!(order.method, "POST");
+        assert_eq!(order.url, "/orders?id=1");
+        assert!(order.headers.contains(&("x-test".to_string(), "value".to_string())));
+        assert_eq!(order.body, "{\"id\": 1}");
+        assert_eq!(order.response.status, 201);
+        assert_eq!(order.response.body.as_deref(), Some("{}"));
+        assert_eq!(entries[1].endpoint_id, testit_lib::journal::UNMATCHED);
+        assert_eq!(entries[1].response.status, 404);
+        let unmatched: Vec<testit_lib::journal::JournalEntry> = serde_json::from_str(client.get("http://localhost:8098/__admin/requests?endpointId=unmatched").send().await.unwrap().text().await.unwrap().as_str()).unwrap();
+        assert_eq!(unmatched.len(), 1);
+        let har: serde_json::Value = serde_json::from_str(&client.get("http://localhost:8098/__admin/har").send().await.unwrap().text().await.unwrap()).unwrap();
+        assert_eq!(har["log"]["entries"][0]["request"]["url"], "http://localhost:8097/orders?id=1");
+        assert_eq!(har["log"]["entries"][0]["response"]["content"]["text"], "{}");
+        assert_eq!(har["log"]["entries"][0]["_endpointId"], order.endpoint_id.as_str());
+        let res = client.delete("http://localhost:8098/__admin/requests").send().await.unwrap();
+        assert_eq!(res.status(), 204);
+        assert!(server_setup.journal().entries().is_empty());
+    }
+
+    /**
+     * Verifying the responses and diagnostics of unmatched requests.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_unmatched() {
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("orders".to_string(), Some(8099), vec![
+                EndpointConfiguration::new("^/orders$".to_string(), "POST".to_string(), None, Some(MockResponseConfiguration::new(None, 201, HashMap::new(), 0)), None),
+                EndpointConfiguration::new("^/orders$".to_string(), "PUT".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 0)), None),
+            ],
+            None),
+            ServerConfiguration::new("fallback".to_string(), Some(8100), vec![], None)
+                .with_fallback(MockResponseConfiguration::new(Some("fallback".to_string()), 503, HashMap::new(), 300)),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        let res = client.get("http://localhost:8099/orders").send().await.unwrap();
+        assert_eq!(res.status(), 405);
+        assert_eq!(res.headers().get("allow").unwrap(), "POST, PUT");
+        let res = client.get("http://localhost:8099/order").send().await.unwrap();
+        assert_eq!(res.status(), 404);
+        let diagnostic: serde_json::Value = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+        assert_eq!(diagnostic["nearMisses"][0]["endpointId"], test_configuration.servers[0].endpoints[0].id.as_str());
+        assert_eq!(diagnostic["nearMisses"][0]["failed"], serde_json::json!(["path", "method"]));
+        let start = std::time::Instant::now();
+        let res = client.get("http://localhost:8100/anything").send().await.unwrap();
+        assert_eq!(res.status(), 503);
+        assert_eq!(res.text().await.unwrap(), "fallback");
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    /**
+     * Verifying that upstream responses of routed endpoints are recorded as mock endpoints, and that upstream
+     * responses replaced by the mock response are not.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_record() {
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "application/json".to_string());
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("upstream".to_string(), Some(8101), vec![
+                EndpointConfiguration::new("^/items$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("[1]".to_string()), 200, headers, 100)), None),
+                EndpointConfiguration::new("^/error$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 503, HashMap::new(), 0)), None),
+            ],
+            None),
+            ServerConfiguration::new("gateway".to_string(), Some(8102), vec![
+                EndpointConfiguration::new("^/error$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("mock".to_string()), 200, HashMap::new(), 0)),
+                    Some(RouteConfiguration::new("http://127.0.0.1:8101".to_string()))).with_route_policy(RoutePolicy::MockOnFailure { timeout: None }),
+                EndpointConfiguration::new("^/.*$".to_string(), "GET".to_string(), None, None, Some(RouteConfiguration::new("http://127.0.0.1:8101".to_string()))),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new().with_recording();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let res = reqwest::get("http://localhost:8102/items?page=1").await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), "[1]");
+        assert_eq!(reqwest::get("http://localhost:8102/missing").await.unwrap().status(), 404);
+        assert_eq!(reqwest::get("http://localhost:8102/error").await.unwrap().text().await.unwrap(), "mock");
+        let recorded = server_setup.recorder().unwrap().test_configuration(&test_configuration);
+        assert_eq!(recorded.servers.len(), 1);
+        assert_eq!(recorded.servers[0].http_port, Some(8102));
+        let endpoints = &recorded.servers[0].endpoints;
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0].endpoint, "^/items$");
+        let mock_response = endpoints[0].mock_response.as_ref().unwrap();
+        assert_eq!(mock_response.response.as_deref(), Some("[1]"));
+        assert_eq!(mock_response.headers.get("content-type").map(String::as_str), Some("application/json"));
+        assert!(mock_response.delay >= 100);
+        assert_eq!(endpoints[1].mock_response.as_ref().unwrap().status, 404);
+    }
+
+    /**
+     * Verifying that the route policies decide between the route and the mock response, and that the timeout does not cut off a streamed body.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_route_policy() {
+        let mock = || Some(MockResponseConfiguration::new(Some("mock".to_string()), 200, HashMap::new(), 0));
+        let upstream = || Some(RouteConfiguration::new("http://127.0.0.1:8103".to_string()));
+        let on_failure = RoutePolicy::MockOnFailure { timeout: Some(200) };
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            ServerConfiguration::new("upstream".to_string(), Some(8103), vec![
+                EndpointConfiguration::new("^/ok$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("upstream".to_string()), 200, HashMap::new(), 0)), None),
+                EndpointConfiguration::new("^/error$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 503, HashMap::new(), 0)), None),
+                EndpointConfiguration::new("^/slow$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 1000)), None),
+                EndpointConfiguration::new("^/flag$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some("upstream".to_string()), 200, HashMap::new(), 0)), None),
+                EndpointConfiguration::new("^/stalled$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_stream(StreamConfiguration::ServerSentEvents { events: vec![
+                    ServerSentEventConfiguration::new(None, "first".to_string(), 0),
+                    ServerSentEventConfiguration::new(None, "second".to_string(), 500),
+                ] })), None),
+            ],
+            None),
+            ServerConfiguration::new("gateway".to_string(), Some(8104), vec![
+                EndpointConfiguration::new("^/(ok|error|slow|stalled)$".to_string(), "GET".to_string(), None, mock(), upstream()).with_route_policy(on_failure.clone()),
+                EndpointConfiguration::new("^/refused$".to_string(), "GET".to_string(), None, mock(), Some(RouteConfiguration::new("http://127.0.0.1:1".to_string()))).with_route_policy(on_failure),
+                EndpointConfiguration::new("^/flag$".to_string(), "GET".to_string(), None, mock(), upstream())
+                    .with_route_policy(RoutePolicy::MockOnHeader { name: "x-use-mock".to_string(), value: Some("true".to_string()) }),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        for (path, expected) in [("ok", "upstream"), ("error", "mock"), ("slow", "mock"), ("refused", "mock"), ("flag", "upstream")] {
+            let res = client.get(format!("http://localhost:8104/{}", path)).send().await.unwrap();
+            assert_eq!(res.status(), 200);
+            assert_eq!(res.text().await.unwrap(), expected, "{}", path);
+        }
+        let res = client.get("http://localhost:8104/flag").header("x-use-mock", "true").send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "mock");
+        let res = client.get("http://localhost:8104/stalled").send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "data: first\n\ndata: second\n\n");
+        let invalid = ServerConfiguration::new("invalid".to_string(), None, vec![
+            EndpointConfiguration::new("^/$".to_string(), "GET".to_string(), None, mock(), None).with_route_policy(RoutePolicy::MockOnFailure { timeout: None }),
+        ], None);
+        assert!(ServerState::new(invalid, Path::new(""), Arc::new(ScenarioStates::new()), Arc::new(RequestJournal::new(0)), None).is_err());
+    }
+
+    /**
+     * Verifying that requests are balanced across the upstream targets and failing targets are ejected.
+     */
+    #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+    async fn test_upstream_targets() {
+        let upstream = |name: &str, port: u16| ServerConfiguration::new(name.to_string(), Some(port), vec![
+            EndpointConfiguration::new("^/.*$".to_string(), "GET".to_string(), None, Some(MockResponseConfiguration::new(Some(name.to_string()), 200, HashMap::new(), 0)), None),
+        ], None);
+        let target = |port: u16| UpstreamTargetConfiguration::new(format!("http://127.0.0.1:{}", port), 1);
+        let test_configuration = TestConfiguration::new("test".to_string(), "test".to_string(),
+        vec![
+            upstream("a", 8105),
+            upstream("b", 8106),
+            ServerConfiguration::new("gateway".to_string(), Some(8107), vec![
+                EndpointConfiguration::new("^/balanced$".to_string(), "GET".to_string(), None, None,
+                    Some(RouteConfiguration::new(String::new()).with_targets(vec![target(8105), target(8106)], LoadBalancingStrategy::RoundRobin))),
+                EndpointConfiguration::new("^/ejected$".to_string(), "GET".to_string(), None, None,
+                    Some(RouteConfiguration::new(String::new()).with_targets(vec![target(1), target(8105)], LoadBalancingStrategy::RoundRobin).with_ejection(EjectionConfiguration::new(1, 60000)))),
+            ],
+            None),
+        ]);
+        let mut server_setup = ServerSetup::new();
+        server_setup.setup_test(&test_configuration).await;
+        let result = server_setup.start_servers().await;
+        assert!(result.is_ok());
+        thread::sleep(Duration::from_secs(1));
+        let client = reqwest::Client::new();
+        let mut responses = vec![];
+        for _ in 0..4 {
+            responses.push(client.get("http://localhost:8107/balanced").send().await.unwrap().text().await.unwrap());
+        }
+        assert_eq!(responses, vec!["a", "b", "a", "b"]);
+        let res = client.get("http://localhost:8107/ejected").send().await.unwrap();
+        assert_eq!(res.status(), 502);
+        for _ in 0..3 {
+            let res = client.get("http://localhost:8107/ejected").send().await.unwrap();
+            assert_eq!(res.status(), 200);
+            assert_eq!(res.text().await.unwrap(), "a");
+        }
+    }
+
 }
@@ -0,0 +1,153 @@
+use std::{convert::Infallible, time::Duration};
+
+use actix_web::{web::Bytes, HttpResponse, HttpResponseBuilder};
+use futures_util::{stream, StreamExt};
+use testit_lib::{config::{MockResponseConfiguration, ServerSentEventConfiguration, StreamConfiguration}, error::ApplicationError};
+
+use crate::template::{self, TemplateContext};
+
+/**
+ * Validate the stream of the mock response.
+ *
+ * # Arguments
+ * @param mock_response: The mock response configuration.
+ *
+ * # Returns
+ * @return Ok if the stream is valid.
+ *
+ * # Errors
+ * @return An error if the mock response has both a stream and a response body.
+ * @return An error if the data of a chunk or event is an invalid template.
+ */
+pub fn validate(mock_response: &MockResponseConfiguration) -> Result<(), ApplicationError> {
+    let Some(stream) = &mock_response.stream else {
+        return Ok(());
+    };
+    if mock_response.response.is_some() || mock_response.response_file.is_some() || mock_response.response_base64.is_some() {
+        return Err(ApplicationError::ConfigurationError("A stream can not be combined with response, responseFile or responseBase64".to_string()));
+    }
+    if mock_response.template {
+        for (_, data) in stream_data(stream) {
+            template::validate(data)?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Create the streamed response. The chunks or events are sent with chunked transfer encoding after their delays.
+ *
+ * # Arguments
+ * @param response_builder: The response builder with the configured status and headers.
+ * @param mock_response: The mock response configuration.
+ * @param stream: The stream configuration.
+ * @param context: The request data used when the data is a template.
+ *
+ * # Returns
+ * @return The streamed response.
+ *
+ * # Errors
+ * @return An error if the data of a chunk or event could not be rendered.
+ */
+pub fn stream_response(mut response_builder: HttpResponseBuilder, mock_response: &MockResponseConfiguration, stream: &StreamConfiguration, context: Option<&TemplateContext>) -> Result<HttpResponse, ApplicationError> {
+    let chunks = stream_data(stream)
+        .map(|(delay, data)| {
+            let data = match context {
+                Some(context) => template::render(data, context)?,
+                None => data.to_string(),
+            };
+            Ok((Duration::from_millis(delay), data))
+        })
+        .collect::<Result<Vec<(Duration, String)>, ApplicationError>>()?;
+    let chunks: Vec<(Duration, Bytes)> = match stream {
+        StreamConfiguration::Chunks { .. } => chunks.into_iter().map(|(delay, data)| (delay, Bytes::from(data))).collect(),
+        StreamConfiguration::ServerSentEvents { events } => events.iter().zip(chunks).map(|(event, (delay, data))| (delay, Bytes::from(format_event(event, &data)))).collect(),
+    };
+    if let StreamConfiguration::ServerSentEvents { .. } = stream {
+        if !mock_response.headers.keys().any(|key| key.eq_ignore_ascii_case("content-type")) {
+            response_builder.insert_header(("content-type", "text/event-stream"));
+        }
+        if !mock_response.headers.keys().any(|key| key.eq_ignore_ascii_case("cache-control")) {
+            response_builder.insert_header(("cache-control", "no-cache"));
+        }
+    }
+    let body = stream::iter(chunks).then(|(delay, bytes)| async move {
+        tokio::time::sleep(delay).await;
+        Ok::<Bytes, Infallible>(bytes)
+    });
+    Ok(response_builder.streaming(body))
+}
+
+/**
+ * Get the delay and data of each chunk or event.
+ *
+ * # Arguments
+ * @param stream: The stream configuration.
+ *
+ * # Returns
+ * @return The delay in milliseconds and the data.
+ */
+fn stream_data(stream: &StreamConfiguration) -> Box<dyn Iterator<Item = (u64, &str)> + '_> {
+    match stream {
+        StreamConfiguration::Chunks { chunks } => Box::new(chunks.iter().map(|chunk| (chunk.delay, chunk.data.as_str()))),
+        StreamConfiguration::ServerSentEvents { events } => Box::new(events.iter().map(|event| (event.delay, event.data.as_str()))),
+    }
+}
+
+/**
+ * Format the event in the Server-Sent Events format.
+ *
+ * # Arguments
+ * @param event: The event configuration.
+ * @param data: The event data.
+ *
+ * # Returns
+ * @return The formatted event.
+ */
+fn format_event(event: &ServerSentEventConfiguration, data: &str) -> String {
+    let mut formatted = String::new();
+    if let Some(name) = &event.event {
+        formatted.push_str(&format!("event: {}\n", name));
+    }
+    if let Some(id) = &event.id {
+        formatted.push_str(&format!("id: {}\n", id));
+    }
+    if let Some(retry) = event.retry {
+        formatted.push_str(&format!("retry: {}\n", retry));
+    }
+    for line in data.lines() {
+        formatted.push_str(&format!("data: {}\n", line));
+    }
+    formatted.push('\n');
+    formatted
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /**
+     * Verifying the Server-Sent Events format.
+     */
+    #[test]
+    fn test_format_event() {
+        let mut event = ServerSentEventConfiguration::new(Some("price".to_string()), "{\"price\": 1}\n{\"price\": 2}".to_string(), 0);
+        event.id = Some("7".to_string());
+        event.retry = Some(1000);
+        assert_eq!(format_event(&event, &event.data), "event: price\nid: 7\nretry: 1000\ndata: {\"price\": 1}\ndata: {\"price\": 2}\n\n");
+        let event = ServerSentEventConfiguration::new(None, "tick".to_string(), 0);
+        assert_eq!(format_event(&event, &event.data), "data: tick\n\n");
+    }
+
+    /**
+     * Verifying that a stream can not be combined with a response body.
+     */
+    #[test]
+    fn test_validate() {
+        let stream = StreamConfiguration::Chunks { chunks: vec![] };
+        assert!(validate(&MockResponseConfiguration::new(None, 200, HashMap::new(), 0).with_stream(stream.clone())).is_ok());
+        assert!(validate(&MockResponseConfiguration::new(Some("{}".to_string()), 200, HashMap::new(), 0).with_stream(stream)).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use actix_web::{web, HttpRequest};
+use chrono::format::{Item, StrftimeItems};
+use jsonpath_rust::{parser::parse_json_path, query::js_path_vals};
+use rand::Rng;
+use regex::Captures;
+use serde_json::Value;
+use sxd_xpath::Factory;
+use testit_lib::error::ApplicationError;
+
+/**
+ * The request data that templates can reference.
+ */
+pub struct TemplateContext<'a> {
+    // The request.
+    request: &'a HttpRequest,
+    // The request body.
+    body: &'a [u8],
+    // The capture groups of the endpoint regular expression.
+    captures: Option<Captures<'a>>,
+}
+
+impl<'a> TemplateContext<'a> {
+    /**
+     * Create a new template context.
+     *
+     * # Arguments
+     * @param request: The request.
+     * @param body: The request body.
+     * @param captures: The capture groups of the endpoint regular expression.
+     *
+     * # Returns
+     * @return The template context.
+     */
+    pub fn new(request: &'a HttpRequest, body: &'a [u8], captures: Option<Captures<'a>>) -> Self {
+        TemplateContext { request, body, captures }
+    }
+}
+
+/**
+ * An expression in a template.
+ */
+enum Expression<'a> {
+    // {{request.method}}
+    Method,
+    // {{request.path}}
+    Path,
+    // {{request.pathSegments.N}}
+    PathSegment(usize),
+    // {{request.captures.N}} or {{request.captures.NAME}}
+    Capture(&'a str),
+    // {{request.query.NAME}}
+    Query(&'a str),
+    // {{request.headers.NAME}}
+    Header(&'a str),
+    // {{request.body}}
+    Body,
+    // {{jsonPath EXPRESSION}}
+    JsonPath(&'a str),
+    // {{xPath EXPRESSION}}
+    XPath(&'a str),
+    // {{now}} or {{now FORMAT}}
+    Now(Option<&'a str>),
+    // {{uuid}}
+    Uuid,
+    // {{randomInt MIN MAX}}
+    RandomInt(i64, i64),
+}
+
+/**
+ * A part of a template.
+ */
+enum Part<'a> {
+    // Text that is copied as is.
+    Literal(&'a str),
+    // An expression that is replaced with request data.
+    Expression(Expression<'a>),
+}
+
+/**
+ * Validate the template. Used at startup so invalid templates are found before requests are received.
+ *
+ * # Arguments
+ * @param template: The template.
+ *
+ * # Returns
+ * @return Ok if the template is valid.
+ *
+ * # Errors
+ * @return An error if the template contains an unknown or invalid expression.
+ */
+pub fn validate(template: &str) -> Result<(), ApplicationError> {
+    parse(template).map(|_| ())
+}
+
+/**
+ * Render the template with the request data.
+ *
+ * # Arguments
+ * @param template: The template.
+ * @param context: The request data.
+ *
+ * # Returns
+ * @return The rendered template.
+ *
+ * # Errors
+ * @return An error if the template contains an unknown expression.
+ */
+pub fn render(template: &str, context: &TemplateContext) -> Result<String, ApplicationError> {
+    let mut rendered = String::with_capacity(template.len());
+    for part in parse(template)? {
+        match part {
+            Part::Literal(literal) => rendered.push_str(literal),
+            Part::Expression(expression) => rendered.push_str(&evaluate(&expression, context)),
+        }
+    }
+    Ok(rendered)
+}
+
+/**
+ * Split the template into literals and expressions.
+ *
+ * # Arguments
+ * @param template: The template.
+ *
+ * # Returns
+ * @return The parts of the template.
+ *
+ * # Errors
+ * @return An error if an expression is not closed or unknown.
+ */
+fn parse(template: &str) -> Result<Vec<Part<'_>>, ApplicationError> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            parts.push(Part::Literal(&rest[..start]));
+        }
+        let end = rest[start..].find("}}").ok_or(ApplicationError::ConfigurationError(format!("Unclosed template expression in: {}", template)))?;
+        parts.push(Part::Expression(parse_expression(rest[start + 2..start + end].trim())?));
+        rest = &rest[start + end + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+    Ok(parts)
+}
+
+/**
+ * Parse a single expression.
+ *
+ * # Arguments
+ * @param expression: The expression without braces.
+ *
+ * # Returns
+ * @return The expression.
+ *
+ * # Errors
+ * @return An error if the expression is unknown.
+ * @return An error if the JSONPath or XPath expression is invalid.
+ * @return An error if the date format is invalid.
+ */
+fn parse_expression(expression: &str) -> Result<Expression<'_>, ApplicationError> {
+    let unknown = || ApplicationError::ConfigurationError(format!("Unknown template expression: {}", expression));
+    let (name, arguments) = match expression.split_once(char::is_whitespace) {
+        Some((name, arguments)) => (name, Some(arguments.trim())),
+        None => (expression, None),
+    };
+    match (name, arguments) {
+        ("request.method", None) => Ok(Expression::Method),
+        ("request.path", None) => Ok(Expression::Path),
+        ("request.body", None) => Ok(Expression::Body),
+        ("jsonPath", Some(path)) => {
+            parse_json_path(path).map_err(|err| ApplicationError::ConfigurationError(format!("Invalid JSONPath in template expression {}: {}", expression, err)))?;
+            Ok(Expression::JsonPath(path))
+        }
+        ("xPath", Some(path)) => {
+            Factory::new().build(path).map_err(|err| ApplicationError::ConfigurationError(format!("Invalid XPath in template expression {}: {}", expression, err)))?;
+            Ok(Expression::XPath(path))
+        }
+        ("now", format) => {
+            if format.is_some_and(|format| StrftimeItems::new(format).any(|item| item == Item::Error)) {
+                return Err(ApplicationError::ConfigurationError(format!("Invalid date format in template expression: {}", expression)));
+            }
+            Ok(Expression::Now(format))
+        }
+        ("uuid", None) => Ok(Expression::Uuid),
+        ("randomInt", Some(arguments)) => {
+            let (min, max) = arguments.split_once(char::is_whitespace).ok_or_else(unknown)?;
+            let min = min.trim().parse::<i64>().map_err(|_| unknown())?;
+            let max = max.trim().parse::<i64>().map_err(|_| unknown())?;
+            if min > max {
+                return Err(unknown());
+            }
+            Ok(Expression::RandomInt(min, max))
+        }
+        (name, None) => {
+            if let Some(index) = name.strip_prefix("request.pathSegments.") {
+                return Ok(Expression::PathSegment(index.parse::<usize>().map_err(|_| unknown())?));
+            }
+            if let Some(capture) = name.strip_prefix("request.captures.") {
+                return Ok(Expression::Capture(capture));
+            }
+            if let Some(query) = name.strip_prefix("request.query.") {
+                return Ok(Expression::Query(query));
+            }
+            if let Some(header) = name.strip_prefix("request.headers.") {
+                return Ok(Expression::Header(header));
+            }
+            Err(unknown())
+        }
+        _ => Err(unknown()),
+    }
+}
+
+/**
+ * Evaluate the expression. Values missing from the request are rendered as an empty string.
+ *
+ * # Arguments
+ * @param expression: The expression.
+ * @param context: The request data.
+ *
+ * # Returns
+ * @return The value of the expression.
+ */
+fn evaluate(expression: &Expression, context: &TemplateContext) -> String {
+    match expression {
+        Expression::Method => context.request.method().to_string(),
+        Expression::Path => context.request.path().to_string(),
+        Expression::PathSegment(index) => context.request.path().split('/').filter(|segment| !segment.is_empty()).nth(*index).unwrap_or_default().to_string(),
+        Expression::Capture(capture) => {
+            let value = match capture.parse::<usize>() {
+                Ok(index) => context.captures.as_ref().and_then(|captures| captures.get(index)),
+                Err(_) => context.captures.as_ref().and_then(|captures| captures.name(capture)),
+            };
+            value.map(|value| value.as_str().to_string()).unwrap_or_default()
+        }
+        Expression::Query(name) => web::Query::<Vec<(String, String)>>::from_query(context.request.query_string())
+            .ok()
+            .and_then(|query| query.into_inner().into_iter().find(|(key, _)| key == name))
+            .map(|(_, value)| value)
+            .unwrap_or_default(),
+        Expression::Header(name) => context.request.headers().get(*name).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string(),
+        Expression::Body => String::from_utf8_lossy(context.body).to_string(),
+        Expression::JsonPath(path) => evaluate_json_path(path, context.body).unwrap_or_default(),
+        Expression::XPath(path) => evaluate_xpath(path, context.body).unwrap_or_default(),
+        Expression::Now(Some(format)) => chrono::Utc::now().format(format).to_string(),
+        Expression::Now(None) => chrono::Utc::now().to_rfc3339(),
+        Expression::Uuid => uuid::Uuid::new_v4().to_string(),
+        Expression::RandomInt(min, max) => rand::thread_rng().gen_range(*min..=*max).to_string(),
+    }
+}
+
+/**
+ * Evaluate a JSONPath expression against the body. Strings are rendered without quotes.
+ *
+ * # Arguments
+ * @param path: The JSONPath expression.
+ * @param body: The request body.
+ *
+ * # Returns
+ * @return The first selected value.
+ */
+fn evaluate_json_path(path: &str, body: &[u8]) -> Option<String> {
+    let json: Value = serde_json::from_slice(body).ok()?;
+    let values = js_path_vals(path, &json).ok()?;
+    match values.first()? {
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+/**
+ * Evaluate an XPath expression against the body.
+ *
+ * # Arguments
+ * @param path: The XPath expression.
+ * @param body: The request body.
+ *
+ * # Returns
+ * @return The string value of the result.
+ */
+fn evaluate_xpath(path: &str, body: &[u8]) -> Option<String> {
+    let package = sxd_document::parser::parse(std::str::from_utf8(body).ok()?).ok()?;
+    let document = package.as_document();
+    let value = sxd_xpath::evaluate_xpath(&document, path).ok()?;
+    Some(value.string())
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+    use regex::Regex;
+
+    use super::*;
+
+    /**
+     * Verifying that request data is rendered.
+     */
+    #[test]
+    fn test_render() {
+        let request = TestRequest::post().uri("/orders/42/lines?q=a%20b").insert_header(("x-tenant", "acme")).to_http_request();
+        let regex = Regex::new("^/orders/(?P<order>[0-9]+)/(.*)$").unwrap();
+        let body = br#"{ "customer": { "name": "Ola" }, "count": 3 }"#;
+        let context = TemplateContext::new(&request, body, regex.captures("/orders/42/lines"));
+        let rendered = render("{{request.method}} {{request.path}} {{request.pathSegments.1}} {{request.captures.order}} {{ request.captures.2 }} {{request.query.q}} {{request.headers.X-Tenant}} {{jsonPath $.customer.name}} {{jsonPath $.count}} {{request.query.missing}}", &context).unwrap();
+        assert_eq!(rendered, "POST /orders/42/lines 42 42 lines a b acme Ola 3 ");
+        let xml = b"<order><id>7</id></order>";
+        let context = TemplateContext::new(&request, xml, None);
+        assert_eq!(render("<id>{{xPath /order/id}}</id>", &context).unwrap(), "<id>7</id>");
+    }
+
+    /**
+     * Verifying the helpers.
+     */
+    #[test]
+    fn test_helpers() {
+        let request = TestRequest::get().to_http_request();
+        let context = TemplateContext::new(&request, &[], None);
+        assert_eq!(render("{{uuid}}", &context).unwrap().len(), 36);
+        assert_eq!(render("{{now %Y}}", &context).unwrap(), chrono::Utc::now().format("%Y").to_string());
+        let random = render("{{randomInt 5 10}}", &context).unwrap().parse::<i64>().unwrap();
+        assert!((5..=10).contains(&random));
+    }
+
+    /**
+     * Verifying that invalid templates fail.
+     */
+    #[test]
+    fn test_validate() {
+        assert!(validate("{{request.method}} {}").is_ok());
+        assert!(validate("{{unknown}}").is_err());
+        assert!(validate("{{request.method").is_err());
+        assert!(validate("{{randomInt 10 5}}").is_err());
+        assert!(validate("{{now %Y-%m-%d}}").is_ok());
+        assert!(validate("{{now %Q}}").is_err());
+        assert!(validate("{{jsonPath $.customer[}}").is_err());
+        assert!(validate("{{xPath /order/[}}").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{error::Error, future::poll_fn, pin::Pin, time::Duration};
+
+use actix_web::{body::{BodySize, BodyStream, BoxBody, MessageBody, SizedStream}, web::{self, Bytes, BytesMut}, HttpResponse};
+use futures_util::{stream, Stream, StreamExt};
+use testit_lib::{config::{EndpointConfiguration, ThrottleConfiguration}, error::ApplicationError};
+
+/**
+ * The number of chunks per second when the body is transferred at a rate of bytes per second.
+ */
+const RATE_CHUNKS_PER_SECOND: u64 = 10;
+
+/**
+ * Validate the throttle configurations of the endpoint.
+ *
+ * # Arguments
+ * @param endpoint: The endpoint configuration.
+ *
+ * # Returns
+ * @return Ok if the throttle configurations are valid.
+ *
+ * # Errors
+ * @return An error if the rate or chunk size is zero.
+ */
+pub fn validate(endpoint: &EndpointConfiguration) -> Result<(), ApplicationError> {
+    for throttle in endpoint.response_throttle.iter().chain(endpoint.request_throttle.iter()) {
+        let valid = match throttle {
+            ThrottleConfiguration::BytesPerSecond { bytes_per_second } => *bytes_per_second > 0,
+            ThrottleConfiguration::Chunks { chunk_size, .. } => *chunk_size > 0,
+        };
+        if !valid {
+            return Err(ApplicationError::ConfigurationError(format!("Endpoint {} has an invalid throttle: {:?}", endpoint.id, throttle)));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Read the request body. If the request is throttled the body is read in chunks with a pause between them.
+ *
+ * # Arguments
+ * @param payload: The request payload.
+ * @param throttle: The throttle configuration.
+ *
+ * # Returns
+ * @return The request body.
+ *
+ * # Errors
+ * @return An error if the request body could not be read.
+ */
+pub async fn read_body(mut payload: web::Payload, throttle: Option<&ThrottleConfiguration>) -> Result<Bytes, ApplicationError> {
+    let mut body = BytesMut::new();
+    let mut unpaused = 0;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|err| ApplicationError::RequestError(err.to_string()))?;
+        body.extend_from_slice(&chunk);
+        if let Some(throttle) = throttle {
+            let chunk_size = chunk_size(throttle);
+            unpaused += chunk.len();
+            while unpaused >= chunk_size {
+                tokio::time::sleep(pause(throttle, chunk_size)).await;
+                unpaused -= chunk_size;
+            }
+        }
+    }
+    Ok(body.freeze())
+}
+
+/**
+ * Throttle the response body. The content length of the response is kept.
+ *
+ * # Arguments
+ * @param response: The response.
+ * @param throttle: The throttle configuration.
+ *
+ * # Returns
+ * @return The throttled response.
+ */
+pub fn throttle_response(response: HttpResponse, throttle: &ThrottleConfiguration) -> HttpResponse {
+    response.map_body(|_, body| {
+        let size = body.size();
+        let stream = throttled_stream(body, throttle.clone());
+        match size {
+            BodySize::Sized(size) => SizedStream::new(size, stream).boxed(),
+            _ => BodyStream::new(stream).boxed(),
+        }
+    })
+}
+
+/**
+ * Get the maximum chunk size.
+ *
+ * # Arguments
+ * @param throttle: The throttle configuration.
+ *
+ * # Returns
+ * @return The chunk size.
+ */
+fn chunk_size(throttle: &ThrottleConfiguration) -> usize {
+    match throttle {
+        ThrottleConfiguration::BytesPerSecond { bytes_per_second } => (*bytes_per_second / RATE_CHUNKS_PER_SECOND).max(1) as usize,
+        ThrottleConfiguration::Chunks { chunk_size, .. } => *chunk_size,
+    }
+}
+
+/**
+ * Get the pause after a chunk. At a rate of bytes per second the pause is the time the chunk takes at that rate.
+ *
+ * # Arguments
+ * @param throttle: The throttle configuration.
+ * @param chunk_len: The length of the chunk.
+ *
+ * # Returns
+ * @return The pause.
+ */
+fn pause(throttle: &ThrottleConfiguration, chunk_len: usize) -> Duration {
+    match throttle {
+        ThrottleConfiguration::BytesPerSecond { bytes_per_second } => Duration::from_secs_f64(chunk_len as f64 / (*bytes_per_second).max(1) as f64),
+        ThrottleConfiguration::Chunks { pause, .. } => Duration::from_millis(*pause),
+    }
+}
+
+/**
+ * Split the body into chunks with a pause before each chunk except the first. The pause is the pause after the
+ * previous chunk.
+ *
+ * # Arguments
+ * @param body: The body.
+ * @param throttle: The throttle configuration.
+ *
+ * # Returns
+ * @return The chunk stream.
+ */
+fn throttled_stream(body: BoxBody, throttle: ThrottleConfiguration) -> impl Stream<Item = Result<Bytes, Box<dyn Error>>> {
+    stream::unfold((body, throttle, Bytes::new(), 0), |(mut body, throttle, mut pending, sent)| async move {
+        while pending.is_empty() {
+            match poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await? {
+                Ok(bytes) => pending = bytes,
+                Err(err) => return Some((Err(err), (body, throttle, Bytes::new(), 0))),
+            }
+        }
+        if sent > 0 {
+            tokio::time::sleep(pause(&throttle, sent)).await;
+        }
+        let chunk = pending.split_to(chunk_size(&throttle).min(pending.len()));
+        let sent = chunk.len();
+        Some((Ok(chunk), (body, throttle, pending, sent)))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use actix_web::body;
+
+    use super::*;
+
+    /**
+     * Verifying that the response body is sent in chunks with pauses.
+     */
+    #[actix_web::test]
+    async fn test_throttle_response() {
+        let response = throttle_response(HttpResponse::Ok().body("0123456789"), &ThrottleConfiguration::Chunks { chunk_size: 2, pause: 50 });
+        assert_eq!(response.body().size(), BodySize::Sized(10));
+        let start = Instant::now();
+        assert_eq!(body::to_bytes(response.into_body()).await.unwrap(), Bytes::from_static(b"0123456789"));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    /**
+     * Verifying the chunk size and pause of the rates.
+     */
+    #[test]
+    fn test_pace() {
+        let throttle = ThrottleConfiguration::BytesPerSecond { bytes_per_second: 1000 };
+        assert_eq!((chunk_size(&throttle), pause(&throttle, 100)), (100, Duration::from_millis(100)));
+        let throttle = ThrottleConfiguration::BytesPerSecond { bytes_per_second: 1999 };
+        assert_eq!(chunk_size(&throttle), 199);
+        assert_eq!(pause(&throttle, 1999), Duration::from_secs(1));
+        let throttle = ThrottleConfiguration::BytesPerSecond { bytes_per_second: 3 };
+        assert_eq!((chunk_size(&throttle), pause(&throttle, 1)), (1, Duration::from_secs_f64(1.0 / 3.0)));
+        let endpoint = EndpointConfiguration::new("/".to_string(), "GET".to_string(), None, None, None).with_request_throttle(ThrottleConfiguration::BytesPerSecond { bytes_per_second: 0 });
+        assert!(validate(&endpoint).is_err());
+    }
+}
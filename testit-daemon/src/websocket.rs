@@ -0,0 +1,280 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use regex::{Regex, RegexSet};
+use testit_lib::{config::{WebSocketConfiguration, WebSocketMessageConfiguration}, error::ApplicationError};
+use tokio::time::Instant;
+
+/**
+ * The WebSocketMatcher is used to find the WebSocket endpoint matching an upgrade request.
+ */
+pub struct WebSocketMatcher {
+    // The compiled WebSocket paths. The index of a pattern is the index of the WebSocket endpoint.
+    paths: RegexSet,
+    // The compiled WebSocket scripts in configuration order.
+    scripts: Vec<Arc<WebSocketScript>>,
+}
+
+impl WebSocketMatcher {
+    /**
+     * Create a new WebSocket matcher.
+     *
+     * # Arguments
+     * @param websockets: The WebSocket configurations.
+     *
+     * # Returns
+     * @return The WebSocket matcher.
+     *
+     * # Errors
+     * @return An error if a path or reply pattern is not a valid regular expression or a close code may not be sent.
+     */
+    pub fn new(websockets: &[WebSocketConfiguration]) -> Result<Self, ApplicationError> {
+        let paths = RegexSet::new(websockets.iter().map(|websocket| websocket.endpoint.as_str())).map_err(|err| ApplicationError::ConfigurationError(err.to_string()))?;
+        let scripts = websockets
+            .iter()
+            .map(|websocket| WebSocketScript::new(websocket).map(Arc::new))
+            .collect::<Result<Vec<Arc<WebSocketScript>>, ApplicationError>>()?;
+        Ok(WebSocketMatcher { paths, scripts })
+    }
+
+    /**
+     * Find the first WebSocket endpoint matching the request.
+     *
+     * # Arguments
+     * @param request: The request.
+     *
+     * # Returns
+     * @return The script of the WebSocket endpoint, or None if the request is not a matching upgrade request.
+     */
+    pub fn find(&self, request: &HttpRequest) -> Option<Arc<WebSocketScript>> {
+        if !is_websocket_upgrade(request) {
+            return None;
+        }
+        let index = self.paths.matches(request.uri().path()).into_iter().next()?;
+        Some(self.scripts[index].clone())
+    }
+}
+
+/**
+ * The compiled script of a WebSocket endpoint.
+ */
+pub struct WebSocketScript {
+    // The WebSocket configuration.
+    configuration: WebSocketConfiguration,
+    // The compiled reply patterns and the messages to send.
+    replies: Vec<(Regex, Vec<WebSocketMessageConfiguration>)>,
+}
+
+impl WebSocketScript {
+    /**
+     * Compile the WebSocket configuration.
+     *
+     * # Arguments
+     * @param configuration: The WebSocket configuration.
+     *
+     * # Returns
+     * @return The WebSocket script.
+     *
+     * # Errors
+     * @return An error if a reply pattern is not a valid regular expression or the close code may not be sent.
+     */
+    fn new(configuration: &WebSocketConfiguration) -> Result<Self, ApplicationError> {
+        if let Some(close) = configuration.close.as_ref().filter(|close| !is_valid_close_code(close.code)) {
+            return Err(ApplicationError::ConfigurationError(format!("Invalid close code {} for {}", close.code, configuration.id)));
+        }
+        let replies = configuration.replies
+            .iter()
+            .map(|reply| {
+                let pattern = Regex::new(&reply.pattern).map_err(|err| ApplicationError::ConfigurationError(format!("Invalid reply pattern {} for {}: {}", reply.pattern, configuration.id, err)))?;
+                Ok((pattern, reply.messages.clone()))
+            })
+            .collect::<Result<Vec<(Regex, Vec<WebSocketMessageConfiguration>)>, ApplicationError>>()?;
+        Ok(WebSocketScript { configuration: configuration.clone(), replies })
+    }
+
+    /**
+     * Get the ID of the WebSocket endpoint.
+     *
+     * # Returns
+     * @return The ID.
+     */
+    pub fn id(&self) -> &str {
+        &self.configuration.id
+    }
+
+    /**
+     * Find the reply to an incoming message.
+     *
+     * # Arguments
+     * @param message: The incoming message.
+     *
+     * # Returns
+     * @return The messages of the first reply with a matching pattern.
+     */
+    fn reply(&self, message: &str) -> Option<&[WebSocketMessageConfiguration]> {
+        self.replies.iter().find(|(pattern, _)| pattern.is_match(message)).map(|(_, messages)| messages.as_slice())
+    }
+}
+
+/**
+ * Check if the request is a WebSocket upgrade request.
+ *
+ * # Arguments
+ * @param request: The request.
+ *
+ * # Returns
+ * @return True if the request asks to upgrade to WebSocket.
+ */
+pub fn is_websocket_upgrade(request: &HttpRequest) -> bool {
+    request.headers().get("upgrade").and_then(|value| value.to_str().ok()).is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/**
+ * Accept the WebSocket upgrade and run the script in the background.
+ *
+ * # Arguments
+ * @param script: The WebSocket script.
+ * @param request: The request.
+ * @param payload: The request payload.
+ *
+ * # Returns
+ * @return The upgrade response.
+ *
+ * # Errors
+ * @return An error if the WebSocket handshake failed.
+ */
+pub fn start_session(script: Arc<WebSocketScript>, request: &HttpRequest, payload: web::Payload) -> Result<HttpResponse, ApplicationError> {
+    let (response, session, stream) = actix_ws::handle(request, payload).map_err(|err| ApplicationError::RequestError(err.to_string()))?;
+    actix_web::rt::spawn(run_script(script, session, stream));
+    Ok(response)
+}
+
+/**
+ * Run the script. The connect messages are sent first, then incoming messages are replied to until the
+ * client closes the connection or the close delay has passed. The close delay also interrupts messages
+ * waiting for their delay.
+ *
+ * # Arguments
+ * @param script: The WebSocket script.
+ * @param session: The session used to send messages.
+ * @param stream: The incoming messages.
+ */
+async fn run_script(script: Arc<WebSocketScript>, mut session: Session, mut stream: MessageStream) {
+    let close_at = script.configuration.close.as_ref().map(|close| Instant::now() + Duration::from_millis(close.delay));
+    let mut pending: &[WebSocketMessageConfiguration] = &script.configuration.on_connect;
+    loop {
+        tokio::select! {
+            _ = wait_until(close_at) => {
+                let reason = script.configuration.close.as_ref().map(|close| CloseReason { code: CloseCode::from(close.code), description: close.reason.clone() });
+                let _ = session.close(reason).await;
+                return;
+            }
+            sent = send_messages(session.clone(), pending), if !pending.is_empty() => {
+                if sent.is_err() {
+                    return;
+                }
+                pending = &[];
+            }
+            message = stream.recv(), if pending.is_empty() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Some(messages) = script.reply(&text) {
+                        pending = messages;
+                    }
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    let _ = session.close(None).await;
+                    return;
+                }
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+}
+
+/**
+ * Wait until the deadline has passed.
+ *
+ * # Arguments
+ * @param deadline: The deadline, or None to wait forever.
+ */
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/**
+ * Send the messages after their delays.
+ *
+ * # Arguments
+ * @param session: The session.
+ * @param messages: The messages.
+ *
+ * # Returns
+ * @return Ok if all messages were sent.
+ *
+ * # Errors
+ * @return An error if the session is closed.
+ */
+async fn send_messages(mut session: Session, messages: &[WebSocketMessageConfiguration]) -> Result<(), actix_ws::Closed> {
+    for message in messages.iter() {
+        tokio::time::sleep(Duration::from_millis(message.delay)).await;
+        session.text(message.message.clone()).await?;
+    }
+    Ok(())
+}
+
+/**
+ * Check if the close code may be sent in a close frame.
+ *
+ * # Arguments
+ * @param code: The close code.
+ *
+ * # Returns
+ * @return True if the code is defined for sending or in the range for libraries and applications.
+ */
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::test::TestRequest;
+    use testit_lib::config::{WebSocketCloseConfiguration, WebSocketReplyConfiguration};
+
+    use super::*;
+
+    /**
+     * Verifying that only upgrade requests match and that replies are found.
+     */
+    #[test]
+    fn test_find() {
+        let websockets = vec![WebSocketConfiguration::new("^/ws$".to_string(), vec![], vec![
+            WebSocketReplyConfiguration::new("^ping".to_string(), vec![WebSocketMessageConfiguration::new("pong".to_string(), 0)]),
+        ], None)];
+        let matcher = WebSocketMatcher::new(&websockets).unwrap();
+        assert!(matcher.find(&TestRequest::get().uri("/ws").to_http_request()).is_none());
+        assert!(matcher.find(&TestRequest::get().uri("/other").insert_header(("upgrade", "websocket")).to_http_request()).is_none());
+        let script = matcher.find(&TestRequest::get().uri("/ws").insert_header(("Upgrade", "WebSocket")).to_http_request()).unwrap();
+        assert_eq!(script.reply("ping 1").unwrap()[0].message, "pong");
+        assert!(script.reply("hello").is_none());
+    }
+
+    /**
+     * Verifying that reserved close codes are rejected.
+     */
+    #[test]
+    fn test_close_code() {
+        for (code, valid) in [(1000, true), (4000, true), (999, false), (1005, false), (1006, false), (1015, false), (2000, false)] {
+            let websockets = vec![WebSocketConfiguration::new("^/ws$".to_string(), vec![], vec![], Some(WebSocketCloseConfiguration::new(code, None, 0)))];
+            assert_eq!(WebSocketMatcher::new(&websockets).is_ok(), valid, "close code {}", code);
+        }
+    }
+}
@@ -53,7 +53,7 @@ impl AppConfiguration {
      * # Errors
      * @return An error if the configuration could not be saved.
      */
-    fn save(&self, path: &str) -> Result<(), ApplicationError> {
+    pub fn save(&self, path: &str) -> Result<(), ApplicationError> {
         let string_data = serde_json::to_string_pretty(&self).map_err(|err| ApplicationError::FileError(err.to_string()))?;
         std::fs::write(path, string_data).map_err(|err| ApplicationError::FileError(err.to_string()))?;
         Ok(())
@@ -90,6 +90,19 @@ pub struct TestConfiguration {
     pub description: String,
     // The server configurations.
     pub servers: Vec<ServerConfiguration>,
+    // The scenarios. The scenario states are shared by all servers in the test.
+    #[serde(default)]
+    pub scenarios: Vec<ScenarioConfiguration>,
+    // The maximum number of requests kept in the request journal. The oldest requests are removed first.
+    #[serde(default = "default_journal_size")]
+    pub journal_size: usize,
+}
+
+/**
+ * The default maximum number of requests kept in the request journal.
+ */
+fn default_journal_size() -> usize {
+    1000
 }
 
 impl TestConfiguration {
@@ -108,8 +121,65 @@ impl TestConfiguration {
             name,
             description,
             servers,
+            scenarios: vec![],
+            journal_size: default_journal_size(),
         }
     }
+
+    /**
+     * Set the scenarios.
+     *
+     * @param scenarios The scenarios.
+     *
+     * @return The test configuration.
+     */
+    pub fn with_scenarios(mut self, scenarios: Vec<ScenarioConfiguration>) -> Self {
+        self.scenarios = scenarios;
+        self
+    }
+
+    /**
+     * Set the maximum number of requests kept in the request journal.
+     *
+     * @param journal_size The maximum number of requests.
+     *
+     * @return The test configuration.
+     */
+    pub fn with_journal_size(mut self, journal_size: usize) -> Self {
+        self.journal_size = journal_size;
+        self
+    }
+}
+
+/**
+ * Configuration for a scenario. A scenario is a state machine where endpoints can require a state and move
+ * the scenario to a new state.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioConfiguration {
+    // The name of the scenario.
+    pub name: String,
+    // The state of the scenario when the test starts.
+    pub initial_state: String,
+    // The valid states. If empty, any state is valid.
+    #[serde(default)]
+    pub states: Vec<String>,
+}
+
+impl ScenarioConfiguration {
+    /**
+     * Create a new scenario configuration.
+     *
+     * @param name The name of the scenario.
+     * @param initial_state The state of the scenario when the test starts.
+     * @param states The valid states.
+     *
+     * @return The scenario configuration.
+     */
+    pub fn new(name: String, initial_state: String, states: Vec<String>) -> Self {
+        ScenarioConfiguration { name, initial_state, states }
+    }
 }
 
 /**
@@ -162,6 +232,16 @@ pub struct ServerConfiguration {
     pub endpoints: Vec<EndpointConfiguration>,
     // The https configuration.
     pub https_config: Option<HttpsConfiguration>,
+    // The seed of the random generator used for delay distributions, chaos, random response sequences, WebSocket relay
+    // faults and weighted upstream targets. Makes the delays, errors and selected responses reproducible.
+    pub seed: Option<u64>,
+    // The errors to inject into requests to endpoints without their own chaos configuration.
+    pub chaos: Option<ChaosConfiguration>,
+    // The WebSocket endpoints. These are matched before the HTTP endpoints.
+    #[serde(default)]
+    pub websockets: Vec<WebSocketConfiguration>,
+    // The response to requests where no path matched or no endpoint matched all criteria. If not set a 404 diagnostic is returned.
+    pub fallback: Option<MockResponseConfiguration>,
 }
 
 impl ServerConfiguration {
@@ -181,9 +261,62 @@ impl ServerConfiguration {
             http_port,
             endpoints,            
             https_config,
+            seed: None,
+            chaos: None,
+            websockets: vec![],
+            fallback: None,
         }
     }
 
+    /**
+     * Set the seed of the random generator used for delay distributions, chaos, random response sequences, WebSocket
+     * relay faults and weighted upstream targets.
+     *
+     * @param seed The seed.
+     *
+     * @return The server configuration.
+     */
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /**
+     * Set the errors to inject into requests to endpoints without their own chaos configuration.
+     *
+     * @param chaos The chaos configuration.
+     *
+     * @return The server configuration.
+     */
+    pub fn with_chaos(mut self, chaos: ChaosConfiguration) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /**
+     * Set the WebSocket endpoints.
+     *
+     * @param websockets The WebSocket endpoints.
+     *
+     * @return The server configuration.
+     */
+    pub fn with_websockets(mut self, websockets: Vec<WebSocketConfiguration>) -> Self {
+        self.websockets = websockets;
+        self
+    }
+
+    /**
+     * Set the response to requests that did not match an endpoint.
+     *
+     * @param fallback The fallback response.
+     *
+     * @return The server configuration.
+     */
+    pub fn with_fallback(mut self, fallback: MockResponseConfiguration) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
 }
 
 /**
@@ -204,6 +337,28 @@ pub struct EndpointConfiguration {
     pub mock_response: Option<MockResponseConfiguration>,
     // The route configuration.
     pub route: Option<RouteConfiguration>,
+    // The header rules that must match the request.
+    #[serde(default)]
+    pub header_matchers: Vec<HeaderMatcherConfiguration>,
+    // The query parameter rules that must match the request.
+    #[serde(default)]
+    pub query_matchers: Vec<QueryMatcherConfiguration>,
+    // The body rules that must match the request.
+    #[serde(default)]
+    pub body_matchers: Vec<BodyMatcherConfiguration>,
+    // The ordered list of mock responses. Used instead of mock_response when the response changes between calls.
+    pub response_sequence: Option<ResponseSequenceConfiguration>,
+    // The scenario state required to match the endpoint and the state to move to after responding.
+    pub scenario: Option<ScenarioStepConfiguration>,
+    // The errors to inject into requests to the endpoint. Replaces the chaos configuration of the server.
+    pub chaos: Option<ChaosConfiguration>,
+    // The rate the response body is sent at. Used for both mock and routed responses.
+    pub response_throttle: Option<ThrottleConfiguration>,
+    // The rate the request body is read at. The body is read before the body matchers are checked, so endpoints that only
+    // differ by their body matchers use the request throttle of the first of them.
+    pub request_throttle: Option<ThrottleConfiguration>,
+    // How the route and the mock response are combined when the endpoint has both. If not set the mock response is used.
+    pub route_policy: Option<RoutePolicy>,
 }
 
 impl EndpointConfiguration {
@@ -230,8 +385,390 @@ impl EndpointConfiguration {
             soap_action,
             mock_response,
             route,
+            header_matchers: vec![],
+            query_matchers: vec![],
+            body_matchers: vec![],
+            response_sequence: None,
+            scenario: None,
+            chaos: None,
+            response_throttle: None,
+            request_throttle: None,
+            route_policy: None,
         }
     }
+
+    /**
+     * Set the header rules that must match the request.
+     *
+     * @param header_matchers The header rules.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_header_matchers(mut self, header_matchers: Vec<HeaderMatcherConfiguration>) -> Self {
+        self.header_matchers = header_matchers;
+        self
+    }
+
+    /**
+     * Set the query parameter rules that must match the request.
+     *
+     * @param query_matchers The query parameter rules.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_query_matchers(mut self, query_matchers: Vec<QueryMatcherConfiguration>) -> Self {
+        self.query_matchers = query_matchers;
+        self
+    }
+
+    /**
+     * Set the body rules that must match the request.
+     *
+     * @param body_matchers The body rules.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_body_matchers(mut self, body_matchers: Vec<BodyMatcherConfiguration>) -> Self {
+        self.body_matchers = body_matchers;
+        self
+    }
+
+    /**
+     * Set the ordered list of mock responses.
+     *
+     * @param response_sequence The response sequence.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_response_sequence(mut self, response_sequence: ResponseSequenceConfiguration) -> Self {
+        self.response_sequence = Some(response_sequence);
+        self
+    }
+
+    /**
+     * Set the scenario state required to match the endpoint and the state to move to after responding.
+     *
+     * @param scenario The scenario step.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_scenario(mut self, scenario: ScenarioStepConfiguration) -> Self {
+        self.scenario = Some(scenario);
+        self
+    }
+
+    /**
+     * Set the errors to inject into requests to the endpoint.
+     *
+     * @param chaos The chaos configuration.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_chaos(mut self, chaos: ChaosConfiguration) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /**
+     * Set the rate the response body is sent at.
+     *
+     * @param response_throttle The throttle configuration.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_response_throttle(mut self, response_throttle: ThrottleConfiguration) -> Self {
+        self.response_throttle = Some(response_throttle);
+        self
+    }
+
+    /**
+     * Set the rate the request body is read at.
+     *
+     * @param request_throttle The throttle configuration.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_request_throttle(mut self, request_throttle: ThrottleConfiguration) -> Self {
+        self.request_throttle = Some(request_throttle);
+        self
+    }
+
+    /**
+     * Set how the route and the mock response are combined.
+     *
+     * @param route_policy The route policy.
+     *
+     * @return The endpoint configuration.
+     */
+    pub fn with_route_policy(mut self, route_policy: RoutePolicy) -> Self {
+        self.route_policy = Some(route_policy);
+        self
+    }
+
+    /**
+     * Get all mock responses of the endpoint, both the single mock response and the response sequence.
+     *
+     * @return The mock responses.
+     */
+    pub fn mock_responses(&self) -> impl Iterator<Item = &MockResponseConfiguration> {
+        self.mock_response
+            .iter()
+            .chain(self.response_sequence.iter().flat_map(|response_sequence| response_sequence.responses.iter()))
+    }
+}
+
+/**
+ * How an endpoint with both a route and a mock response decides which one to use.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum RoutePolicy {
+    // Forward the request to the route, and use the mock response if the upstream can not be reached, does not
+    // send the response headers within the timeout in milliseconds or returns a 5xx status.
+    MockOnFailure { timeout: Option<u64> },
+    // Use the mock response if the request has the header, otherwise forward the request to the route. If the
+    // value is set the header must have the value.
+    MockOnHeader { name: String, value: Option<String> },
+}
+
+/**
+ * Configuration for the rate a body is transferred at.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ThrottleConfiguration {
+    // Transfer the body at a fixed rate.
+    BytesPerSecond { bytes_per_second: u64 },
+    // Transfer the body in chunks with a pause in milliseconds between them.
+    Chunks { chunk_size: usize, pause: u64 },
+}
+
+/**
+ * Configuration for injecting errors into a percentage of the requests. Used for both mocked and routed endpoints.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChaosConfiguration {
+    // The percentage of requests to inject errors into, from 0 to 100.
+    pub percentage: f64,
+    // The status to return instead of the response.
+    pub status: Option<u16>,
+    // The connection fault to simulate instead of the response.
+    pub fault: Option<Fault>,
+    // Extra time to wait in milliseconds before the request is handled.
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl ChaosConfiguration {
+    /**
+     * Create a new chaos configuration.
+     *
+     * @param percentage The percentage of requests to inject errors into, from 0 to 100.
+     *
+     * @return The chaos configuration.
+     */
+    pub fn new(percentage: f64) -> Self {
+        ChaosConfiguration {
+            percentage,
+            status: None,
+            fault: None,
+            delay: 0,
+        }
+    }
+
+    /**
+     * Set the status to return instead of the response.
+     *
+     * @param status The status.
+     *
+     * @return The chaos configuration.
+     */
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /**
+     * Set the connection fault to simulate instead of the response.
+     *
+     * @param fault The fault.
+     *
+     * @return The chaos configuration.
+     */
+    pub fn with_fault(mut self, fault: Fault) -> Self {
+        self.fault = Some(fault);
+        self
+    }
+
+    /**
+     * Set the extra time to wait before the request is handled.
+     *
+     * @param delay The delay in milliseconds.
+     *
+     * @return The chaos configuration.
+     */
+    pub fn with_delay(mut self, delay: u64) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/**
+ * Rule for matching a request value.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MatchRule {
+    // The value must be equal.
+    Equals { value: String },
+    // The value must match the regular expression.
+    Regex { pattern: String },
+    // All the values must be present. Used for values that are repeated.
+    Includes { values: Vec<String> },
+    // The value must be present.
+    Present,
+    // The value must be absent.
+    Absent,
+}
+
+/**
+ * Configuration for matching a request header.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HeaderMatcherConfiguration {
+    // The header name. This is case insensitive.
+    pub name: String,
+    // The rule the header must match.
+    #[serde(flatten)]
+    pub rule: MatchRule,
+}
+
+impl HeaderMatcherConfiguration {
+    /**
+     * Create a new header matcher configuration.
+     *
+     * @param name The header name.
+     * @param rule The rule the header must match.
+     *
+     * @return The header matcher configuration.
+     */
+    pub fn new(name: String, rule: MatchRule) -> Self {
+        HeaderMatcherConfiguration { name, rule }
+    }
+}
+
+/**
+ * Configuration for matching a request query parameter.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMatcherConfiguration {
+    // The query parameter name. This is case sensitive.
+    pub name: String,
+    // The rule the query parameter must match.
+    #[serde(flatten)]
+    pub rule: MatchRule,
+}
+
+impl QueryMatcherConfiguration {
+    /**
+     * Create a new query matcher configuration.
+     *
+     * @param name The query parameter name.
+     * @param rule The rule the query parameter must match.
+     *
+     * @return The query matcher configuration.
+     */
+    pub fn new(name: String, rule: MatchRule) -> Self {
+        QueryMatcherConfiguration { name, rule }
+    }
+}
+
+/**
+ * Configuration for matching the request body.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum BodyMatcherConfiguration {
+    // The body must be a JSON document equal to the json value. Fields not in the json value are ignored if ignore_extra_fields is set.
+    EqualToJson { json: serde_json::Value, #[serde(default)] ignore_extra_fields: bool },
+    // The JSONPath expression must select at least one value in the body.
+    JsonPath { expression: String },
+    // The XPath expression must select at least one node or evaluate to true. The namespaces map prefixes to namespace URIs.
+    XPath { expression: String, #[serde(default)] namespaces: HashMap<String, String> },
+    // The body must match the regular expression.
+    Regex { pattern: String },
+}
+
+/**
+ * Configuration for an endpoint taking part in a scenario.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioStepConfiguration {
+    // The name of the scenario.
+    pub name: String,
+    // The state the scenario must be in for the endpoint to match. If not set, the endpoint matches in any state.
+    pub required_state: Option<String>,
+    // The state to move the scenario to after responding. If not set, the state is not changed.
+    pub new_state: Option<String>,
+}
+
+impl ScenarioStepConfiguration {
+    /**
+     * Create a new scenario step configuration.
+     *
+     * @param name The name of the scenario.
+     * @param required_state The state the scenario must be in for the endpoint to match.
+     * @param new_state The state to move the scenario to after responding.
+     *
+     * @return The scenario step configuration.
+     */
+    pub fn new(name: String, required_state: Option<String>, new_state: Option<String>) -> Self {
+        ScenarioStepConfiguration { name, required_state, new_state }
+    }
+}
+
+/**
+ * How the next response of a response sequence is selected.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum SequenceMode {
+    // The responses are returned in order, and the last response is returned for all following calls.
+    Sequential,
+    // The responses are returned in order, starting over after the last response.
+    Cycle,
+    // A random response is returned. The chance of a response is its weight divided by the sum of all weights.
+    Random,
+}
+
+/**
+ * Configuration for an ordered list of mock responses.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseSequenceConfiguration {
+    // How the next response is selected.
+    pub mode: SequenceMode,
+    // The responses.
+    pub responses: Vec<MockResponseConfiguration>,
+}
+
+impl ResponseSequenceConfiguration {
+    /**
+     * Create a new response sequence configuration.
+     *
+     * @param mode How the next response is selected.
+     * @param responses The responses.
+     *
+     * @return The response sequence configuration.
+     */
+    pub fn new(mode: SequenceMode, responses: Vec<MockResponseConfiguration>) -> Self {
+        ResponseSequenceConfiguration { mode, responses }
+    }
 }
 
 /**
@@ -248,6 +785,144 @@ pub struct MockResponseConfiguration {
     pub headers: HashMap<String, String>,
     // Time to wait in milliseconds before returning the response.
     pub delay: u64,
+    // If the response and header values are templates that can reference the request.
+    #[serde(default)]
+    pub template: bool,
+    // The file to return as the response body. Relative paths are resolved from the configuration file.
+    pub response_file: Option<String>,
+    // The base64 encoded response body. Used for binary content.
+    pub response_base64: Option<String>,
+    // The weight of the response when a response sequence selects responses at random.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    // The connection fault to simulate instead of returning a normal response.
+    pub fault: Option<Fault>,
+    // The distribution of a random delay in milliseconds that is added to the fixed delay.
+    pub delay_distribution: Option<DelayDistribution>,
+    // The chunks or events to stream as the response body.
+    pub stream: Option<StreamConfiguration>,
+}
+
+/**
+ * Configuration for a streamed response body. The body is sent with chunked transfer encoding.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum StreamConfiguration {
+    // Send the chunks as they are.
+    Chunks { chunks: Vec<ChunkConfiguration> },
+    // Send the events in the Server-Sent Events format.
+    ServerSentEvents { events: Vec<ServerSentEventConfiguration> },
+}
+
+/**
+ * Configuration for a chunk of a streamed response.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkConfiguration {
+    // The data of the chunk.
+    pub data: String,
+    // Time to wait in milliseconds before the chunk is sent.
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl ChunkConfiguration {
+    /**
+     * Create a new chunk configuration.
+     *
+     * @param data The data of the chunk.
+     * @param delay Time to wait in milliseconds before the chunk is sent.
+     *
+     * @return The chunk configuration.
+     */
+    pub fn new(data: String, delay: u64) -> Self {
+        ChunkConfiguration { data, delay }
+    }
+}
+
+/**
+ * Configuration for a Server-Sent Event.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerSentEventConfiguration {
+    // The event type.
+    pub event: Option<String>,
+    // The event data. Multiple lines are sent as multiple data fields.
+    pub data: String,
+    // The event id.
+    pub id: Option<String>,
+    // The reconnection time in milliseconds.
+    pub retry: Option<u64>,
+    // Time to wait in milliseconds before the event is sent.
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl ServerSentEventConfiguration {
+    /**
+     * Create a new Server-Sent Event configuration.
+     *
+     * @param event The event type.
+     * @param data The event data.
+     * @param delay Time to wait in milliseconds before the event is sent.
+     *
+     * @return The Server-Sent Event configuration.
+     */
+    pub fn new(event: Option<String>, data: String, delay: u64) -> Self {
+        ServerSentEventConfiguration {
+            event,
+            data,
+            id: None,
+            retry: None,
+            delay,
+        }
+    }
+}
+
+/**
+ * A distribution of delays in milliseconds. Negative samples are used as zero.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum DelayDistribution {
+    // A delay between min and max, inclusive.
+    Uniform { min: u64, max: u64 },
+    // A normal distribution.
+    Normal { mean: f64, standard_deviation: f64 },
+    // A log-normal distribution. Sigma is the standard deviation of the logarithm of the delay.
+    LogNormal { median: f64, sigma: f64 },
+    // Delays with the percentiles. Delays are interpolated linearly from 0 to p50, p95 and p99, so the fastest half
+    // of the delays is spread down to 0. The slowest 1% of the delays is p99 and no delay exceeds it, so p99 must be
+    // the longest delay of the tail.
+    Percentiles { p50: u64, p95: u64, p99: u64 },
+}
+
+/**
+ * A connection fault. Faults are executed on the socket, so the client sees the same errors as with a failing network or service.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum Fault {
+    // Reset the connection without sending a response.
+    ConnectionReset,
+    // Close the connection after sending the status and headers, but before the body is complete.
+    CloseAfterHeaders,
+    // Close the connection without sending a response.
+    EmptyResponse,
+    // Send random data that is not a valid HTTP response and close the connection.
+    MalformedResponse,
+    // Never respond and keep the connection open.
+    Hang,
+}
+
+/**
+ * The default weight of a mock response.
+ */
+fn default_weight() -> u32 {
+    1
 }
 
 impl MockResponseConfiguration {
@@ -272,8 +947,224 @@ impl MockResponseConfiguration {
             status,
             headers,
             delay,
+            template: false,
+            response_file: None,
+            response_base64: None,
+            weight: default_weight(),
+            fault: None,
+            delay_distribution: None,
+            stream: None,
         }
     }
+
+    /**
+     * Set if the response and header values are templates that can reference the request.
+     *
+     * @param template If the response is a template.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_template(mut self, template: bool) -> Self {
+        self.template = template;
+        self
+    }
+
+    /**
+     * Set the file to return as the response body.
+     *
+     * @param response_file The path to the file. Relative paths are resolved from the configuration file.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_response_file(mut self, response_file: String) -> Self {
+        self.response_file = Some(response_file);
+        self
+    }
+
+    /**
+     * Set the base64 encoded response body.
+     *
+     * @param response_base64 The base64 encoded response body.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_response_base64(mut self, response_base64: String) -> Self {
+        self.response_base64 = Some(response_base64);
+        self
+    }
+
+    /**
+     * Set the weight of the response when a response sequence selects responses at random.
+     *
+     * @param weight The weight.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /**
+     * Set the connection fault to simulate.
+     *
+     * @param fault The fault.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_fault(mut self, fault: Fault) -> Self {
+        self.fault = Some(fault);
+        self
+    }
+
+    /**
+     * Set the distribution of the random delay that is added to the fixed delay.
+     *
+     * @param delay_distribution The delay distribution.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_delay_distribution(mut self, delay_distribution: DelayDistribution) -> Self {
+        self.delay_distribution = Some(delay_distribution);
+        self
+    }
+
+    /**
+     * Set the chunks or events to stream as the response body.
+     *
+     * @param stream The stream configuration.
+     *
+     * @return The mock response configuration.
+     */
+    pub fn with_stream(mut self, stream: StreamConfiguration) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+}
+
+/**
+ * Configuration for a WebSocket endpoint. Matching upgrade requests are accepted and the script is run.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketConfiguration {
+    // The ID of the WebSocket endpoint. This is a UUID automatically generated.
+    pub id: String,
+    // The path of the WebSocket endpoint. This is a regular expression.
+    pub endpoint: String,
+    // The messages to send when the client connects.
+    #[serde(default)]
+    pub on_connect: Vec<WebSocketMessageConfiguration>,
+    // The replies to incoming text messages. The first reply with a matching pattern is used.
+    #[serde(default)]
+    pub replies: Vec<WebSocketReplyConfiguration>,
+    // Close the connection after a delay.
+    pub close: Option<WebSocketCloseConfiguration>,
+}
+
+impl WebSocketConfiguration {
+    /**
+     * Create a new WebSocket configuration.
+     *
+     * @param endpoint The path of the WebSocket endpoint. This is a regular expression.
+     * @param on_connect The messages to send when the client connects.
+     * @param replies The replies to incoming text messages.
+     * @param close Close the connection after a delay.
+     *
+     * @return The WebSocket configuration.
+     */
+    pub fn new(endpoint: String, on_connect: Vec<WebSocketMessageConfiguration>, replies: Vec<WebSocketReplyConfiguration>, close: Option<WebSocketCloseConfiguration>) -> Self {
+        WebSocketConfiguration {
+            id: Uuid::new_v4().to_string(),
+            endpoint,
+            on_connect,
+            replies,
+            close,
+        }
+    }
+}
+
+/**
+ * Configuration for a WebSocket text message.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketMessageConfiguration {
+    // The message.
+    pub message: String,
+    // Time to wait in milliseconds before the message is sent.
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl WebSocketMessageConfiguration {
+    /**
+     * Create a new WebSocket message configuration.
+     *
+     * @param message The message.
+     * @param delay Time to wait in milliseconds before the message is sent.
+     *
+     * @return The WebSocket message configuration.
+     */
+    pub fn new(message: String, delay: u64) -> Self {
+        WebSocketMessageConfiguration { message, delay }
+    }
+}
+
+/**
+ * Configuration for replies to incoming WebSocket messages.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketReplyConfiguration {
+    // The regular expression the incoming message must match.
+    pub pattern: String,
+    // The messages to send.
+    pub messages: Vec<WebSocketMessageConfiguration>,
+}
+
+impl WebSocketReplyConfiguration {
+    /**
+     * Create a new WebSocket reply configuration.
+     *
+     * @param pattern The regular expression the incoming message must match.
+     * @param messages The messages to send.
+     *
+     * @return The WebSocket reply configuration.
+     */
+    pub fn new(pattern: String, messages: Vec<WebSocketMessageConfiguration>) -> Self {
+        WebSocketReplyConfiguration { pattern, messages }
+    }
+}
+
+/**
+ * Configuration for closing a WebSocket connection.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketCloseConfiguration {
+    // The close code.
+    pub code: u16,
+    // The close reason.
+    pub reason: Option<String>,
+    // Time to wait in milliseconds after the client connects before the connection is closed.
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl WebSocketCloseConfiguration {
+    /**
+     * Create a new WebSocket close configuration.
+     *
+     * @param code The close code.
+     * @param reason The close reason.
+     * @param delay Time to wait in milliseconds after the client connects before the connection is closed.
+     *
+     * @return The WebSocket close configuration.
+     */
+    pub fn new(code: u16, reason: Option<String>, delay: u64) -> Self {
+        WebSocketCloseConfiguration { code, reason, delay }
+    }
 }
 
 /**
@@ -282,8 +1173,19 @@ impl MockResponseConfiguration {
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RouteConfiguration {
-    // The URL of the endpoint.
+    // The URL of the endpoint. Used when no targets are configured.
+    #[serde(default)]
     pub endpoint: String,
+    // The delay and fault applied to each relayed WebSocket message.
+    pub websocket: Option<WebSocketRelayConfiguration>,
+    // The upstream targets the requests are balanced across. Replaces the endpoint.
+    #[serde(default)]
+    pub targets: Vec<UpstreamTargetConfiguration>,
+    // How the target of each request is selected.
+    #[serde(default)]
+    pub strategy: LoadBalancingStrategy,
+    // When targets that keep failing are taken out of rotation.
+    pub ejection: Option<EjectionConfiguration>,
 }
 
 impl RouteConfiguration {
@@ -295,7 +1197,170 @@ impl RouteConfiguration {
      * @return The route configuration.
      */
     pub fn new(endpoint: String) -> Self {
-        RouteConfiguration { endpoint }
+        RouteConfiguration { endpoint, websocket: None, targets: Vec::new(), strategy: LoadBalancingStrategy::RoundRobin, ejection: None }
+    }
+
+    /**
+     * Set the delay and fault applied to each relayed WebSocket message.
+     *
+     * @param websocket The WebSocket relay configuration.
+     *
+     * @return The route configuration.
+     */
+    pub fn with_websocket(mut self, websocket: WebSocketRelayConfiguration) -> Self {
+        self.websocket = Some(websocket);
+        self
+    }
+
+    /**
+     * Set the upstream targets and how the target of each request is selected.
+     *
+     * @param targets The upstream targets.
+     * @param strategy How the target is selected.
+     *
+     * @return The route configuration.
+     */
+    pub fn with_targets(mut self, targets: Vec<UpstreamTargetConfiguration>, strategy: LoadBalancingStrategy) -> Self {
+        self.targets = targets;
+        self.strategy = strategy;
+        self
+    }
+
+    /**
+     * Set when targets that keep failing are taken out of rotation.
+     *
+     * @param ejection The ejection configuration.
+     *
+     * @return The route configuration.
+     */
+    pub fn with_ejection(mut self, ejection: EjectionConfiguration) -> Self {
+        self.ejection = Some(ejection);
+        self
+    }
+}
+
+/**
+ * Configuration for an upstream target of a route.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamTargetConfiguration {
+    // The URL of the target.
+    pub endpoint: String,
+    // The weight of the target with the weighted strategy. The chance of a target is its weight divided by the sum of all weights.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+impl UpstreamTargetConfiguration {
+    /**
+     * Create a new upstream target configuration.
+     *
+     * @param endpoint The URL of the target.
+     * @param weight The weight of the target with the weighted strategy.
+     *
+     * @return The upstream target configuration.
+     */
+    pub fn new(endpoint: String, weight: u32) -> Self {
+        UpstreamTargetConfiguration { endpoint, weight }
+    }
+}
+
+/**
+ * How the upstream target of a request is selected.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum LoadBalancingStrategy {
+    // The targets are selected in order, starting over after the last target.
+    #[default]
+    RoundRobin,
+    // A random target is selected.
+    Random,
+    // The target with the fewest requests in progress is selected. The first target is selected on ties.
+    LeastConnections,
+    // A random target is selected using the target weights.
+    Weighted,
+}
+
+/**
+ * Configuration for passive ejection of upstream targets. A target is ejected when it fails a number of requests in a row,
+ * where a failure is a connection error, a timeout or a 5xx status.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EjectionConfiguration {
+    // The number of failures in a row before the target is ejected.
+    pub consecutive_failures: u32,
+    // Time in milliseconds the target is ejected for.
+    pub duration: u64,
+}
+
+impl EjectionConfiguration {
+    /**
+     * Create a new ejection configuration.
+     *
+     * @param consecutive_failures The number of failures in a row before the target is ejected.
+     * @param duration Time in milliseconds the target is ejected for.
+     *
+     * @return The ejection configuration.
+     */
+    pub fn new(consecutive_failures: u32, duration: u64) -> Self {
+        EjectionConfiguration { consecutive_failures, duration }
+    }
+}
+
+/**
+ * Configuration for the messages relayed between a WebSocket client and the upstream service.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketRelayConfiguration {
+    // Time to wait in milliseconds before each message is relayed.
+    #[serde(default)]
+    pub delay: u64,
+    // The connection fault to execute instead of relaying a message. Only connection reset, empty response and hang are supported.
+    pub fault: Option<Fault>,
+    // The percentage of messages the fault is executed for, from 0 to 100.
+    #[serde(default = "default_fault_percentage")]
+    pub fault_percentage: f64,
+}
+
+/**
+ * The default percentage of relayed messages a fault is executed for.
+ */
+fn default_fault_percentage() -> f64 {
+    100.0
+}
+
+impl WebSocketRelayConfiguration {
+    /**
+     * Create a new WebSocket relay configuration.
+     *
+     * @param delay Time to wait in milliseconds before each message is relayed.
+     *
+     * @return The WebSocket relay configuration.
+     */
+    pub fn new(delay: u64) -> Self {
+        WebSocketRelayConfiguration {
+            delay,
+            fault: None,
+            fault_percentage: default_fault_percentage(),
+        }
+    }
+
+    /**
+     * Set the connection fault to execute instead of relaying a message.
+     *
+     * @param fault The fault.
+     * @param fault_percentage The percentage of messages the fault is executed for, from 0 to 100.
+     *
+     * @return The WebSocket relay configuration.
+     */
+    pub fn with_fault(mut self, fault: Fault, fault_percentage: f64) -> Self {
+        self.fault = Some(fault);
+        self.fault_percentage = fault_percentage;
+        self
     }
 }
 
@@ -428,6 +1493,97 @@ mod test {
         assert_eq!(configuration, deserialized);
     }
 
+    /**
+     * Test deserializing header matchers.
+     */
+    #[test]
+    fn test_deserialize_header_matchers() {
+        let endpoint: EndpointConfiguration = serde_json::from_str(r#"{
+            "id": "1",
+            "endpoint": "/test",
+            "method": "GET",
+            "headerMatchers": [
+                { "name": "X-Tenant", "type": "equals", "value": "acme" },
+                { "name": "Accept", "type": "regex", "pattern": "json" },
+                { "name": "Authorization", "type": "absent" }
+            ]
+        }"#).unwrap();
+
+        assert_eq!(endpoint.header_matchers, vec![
+            HeaderMatcherConfiguration::new("X-Tenant".to_string(), MatchRule::Equals { value: "acme".to_string() }),
+            HeaderMatcherConfiguration::new("Accept".to_string(), MatchRule::Regex { pattern: "json".to_string() }),
+            HeaderMatcherConfiguration::new("Authorization".to_string(), MatchRule::Absent),
+        ]);
+    }
+
+    /**
+     * Test deserializing body matchers.
+     */
+    #[test]
+    fn test_deserialize_body_matchers() {
+        let endpoint: EndpointConfiguration = serde_json::from_str(r#"{
+            "id": "1",
+            "endpoint": "/test",
+            "method": "POST",
+            "bodyMatchers": [
+                { "type": "equalToJson", "json": { "id": 1 }, "ignoreExtraFields": true },
+                { "type": "jsonPath", "expression": "$.id" },
+                { "type": "xPath", "expression": "//s:Body", "namespaces": { "s": "http://schemas.xmlsoap.org/soap/envelope/" } },
+                { "type": "regex", "pattern": "id" }
+            ]
+        }"#).unwrap();
+
+        assert_eq!(endpoint.body_matchers, vec![
+            BodyMatcherConfiguration::EqualToJson { json: serde_json::json!({ "id": 1 }), ignore_extra_fields: true },
+            BodyMatcherConfiguration::JsonPath { expression: "$.id".to_string() },
+            BodyMatcherConfiguration::XPath { expression: "//s:Body".to_string(), namespaces: HashMap::from([("s".to_string(), "http://schemas.xmlsoap.org/soap/envelope/".to_string())]) },
+            BodyMatcherConfiguration::Regex { pattern: "id".to_string() },
+        ]);
+    }
+
+    /**
+     * Test deserializing a response sequence.
+     */
+    #[test]
+    fn test_deserialize_response_sequence() {
+        let endpoint: EndpointConfiguration = serde_json::from_str(r#"{
+            "id": "1",
+            "endpoint": "/test",
+            "method": "GET",
+            "responseSequence": {
+                "mode": "random",
+                "responses": [
+                    { "status": 500, "headers": {}, "delay": 0, "weight": 3 },
+                    { "status": 200, "headers": {}, "delay": 0 }
+                ]
+            }
+        }"#).unwrap();
+
+        let response_sequence = endpoint.response_sequence.as_ref().unwrap();
+        assert_eq!(response_sequence.mode, SequenceMode::Random);
+        assert_eq!(response_sequence.responses[0].weight, 3);
+        assert_eq!(response_sequence.responses[1].weight, 1);
+        assert_eq!(endpoint.mock_responses().count(), 2);
+    }
+
+    /**
+     * Test deserializing delay distributions.
+     */
+    #[test]
+    fn test_deserialize_delay_distribution() {
+        let mock_responses: Vec<MockResponseConfiguration> = serde_json::from_str(r#"[
+            { "status": 200, "headers": {}, "delay": 0, "delayDistribution": { "type": "uniform", "min": 10, "max": 20 } },
+            { "status": 200, "headers": {}, "delay": 0, "delayDistribution": { "type": "normal", "mean": 100, "standardDeviation": 15.5 } },
+            { "status": 200, "headers": {}, "delay": 0, "delayDistribution": { "type": "logNormal", "median": 80, "sigma": 0.5 } },
+            { "status": 200, "headers": {}, "delay": 0, "delayDistribution": { "type": "percentiles", "p50": 20, "p95": 150, "p99": 900 } }
+        ]"#).unwrap();
+
+        assert_eq!(mock_responses[0].delay_distribution, Some(DelayDistribution::Uniform { min: 10, max: 20 }));
+        assert_eq!(mock_responses[1].delay_distribution, Some(DelayDistribution::Normal { mean: 100.0, standard_deviation: 15.5 }));
+        assert_eq!(mock_responses[2].delay_distribution, Some(DelayDistribution::LogNormal { median: 80.0, sigma: 0.5 }));
+        assert_eq!(mock_responses[3].delay_distribution, Some(DelayDistribution::Percentiles { p50: 20, p95: 150, p99: 900 }));
+    }
+
     #[test]
     fn test_save_load() {
         let configuration = AppConfiguration::new(
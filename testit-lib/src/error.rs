@@ -8,6 +8,9 @@ pub enum ApplicationError {
       CouldNotFindTest(String),
       ConfigurationError(String),
       ServerStartUpError(String),
+      UpstreamError(String),
+      FaultError(String),
+      RequestError(String),
 }
 
 /**
@@ -21,6 +24,9 @@ impl std::fmt::Display for ApplicationError {
             ApplicationError::CouldNotFindTest(err) => write!(f, "Could not find test: {}", err),
             ApplicationError::ConfigurationError(err) => write!(f, "Configuration error: {}", err),
             ApplicationError::ServerStartUpError(err) => write!(f, "Server start up error: {}", err),
+            ApplicationError::UpstreamError(err) => write!(f, "Upstream error: {}", err),
+            ApplicationError::FaultError(err) => write!(f, "Fault error: {}", err),
+            ApplicationError::RequestError(err) => write!(f, "Request error: {}", err),
         }
     }
 }
\ No newline at end of file
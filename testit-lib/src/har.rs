@@ -0,0 +1,348 @@
+use actix_web::{http::StatusCode, web::Query};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ApplicationError, journal::JournalEntry};
+
+/**
+ * The HAR version written.
+ */
+const HAR_VERSION: &str = "1.2";
+
+/**
+ * The HTTP version written for requests and responses. The journal does not record the version.
+ */
+const HTTP_VERSION: &str = "HTTP/1.1";
+
+/**
+ * An HTTP Archive (HAR) 1.2 document with captured traffic.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Har {
+    // The archive.
+    pub log: HarLog,
+}
+
+impl Har {
+    /**
+     * Create a HAR document from the journal entries.
+     *
+     * @param entries The journal entries.
+     *
+     * @return The HAR document.
+     */
+    pub fn new(entries: &[JournalEntry]) -> Self {
+        Har {
+            log: HarLog {
+                version: HAR_VERSION.to_string(),
+                creator: HarCreator {
+                    name: "testit".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                entries: entries.iter().map(HarEntry::new).collect(),
+            },
+        }
+    }
+
+    /**
+     * Save the HAR document to a file.
+     *
+     * @param path The path to save the HAR document to.
+     *
+     * @return Ok if the HAR document was saved successfully.
+     *
+     * # Errors
+     * @return An error if the HAR document could not be saved.
+     */
+    pub fn save(&self, path: &str) -> Result<(), ApplicationError> {
+        let string_data = serde_json::to_string_pretty(&self).map_err(|err| ApplicationError::FileError(err.to_string()))?;
+        std::fs::write(path, string_data).map_err(|err| ApplicationError::FileError(err.to_string()))?;
+        Ok(())
+    }
+}
+
+/**
+ * The root of the HAR document.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarLog {
+    // The HAR format version.
+    pub version: String,
+    // The application that created the document.
+    pub creator: HarCreator,
+    // The requests with the oldest first.
+    pub entries: Vec<HarEntry>,
+}
+
+/**
+ * The application that created the HAR document.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarCreator {
+    // The name of the application.
+    pub name: String,
+    // The version of the application.
+    pub version: String,
+}
+
+/**
+ * A request and its response.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarEntry {
+    // The time the request was received.
+    pub started_date_time: String,
+    // The total time of the request in milliseconds.
+    pub time: u64,
+    // The request.
+    pub request: HarRequest,
+    // The response.
+    pub response: HarResponse,
+    // The cache state. Always empty since the servers do not cache.
+    pub cache: HarCache,
+    // The time spent in each phase of the request.
+    pub timings: HarTimings,
+    // The ID of the server that received the request.
+    #[serde(rename = "_serverId")]
+    pub server_id: String,
+    // The ID of the matched endpoint, or unmatched.
+    #[serde(rename = "_endpointId")]
+    pub endpoint_id: String,
+}
+
+impl HarEntry {
+    /**
+     * Create a HAR entry from the journal entry.
+     *
+     * @param entry The journal entry.
+     *
+     * @return The HAR entry.
+     */
+    fn new(entry: &JournalEntry) -> Self {
+        let host = header_value(&entry.headers, "host").unwrap_or("localhost");
+        let query = entry.url.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        let request_content_type = header_value(&entry.headers, "content-type");
+        let response_content_type = header_value(&entry.response.headers, "content-type").unwrap_or_default();
+        HarEntry {
+            started_date_time: entry.timestamp.clone(),
+            time: entry.response.latency,
+            request: HarRequest {
+                method: entry.method.clone(),
+                url: format!("{}://{}{}", entry.scheme, host, entry.url),
+                http_version: HTTP_VERSION.to_string(),
+                cookies: vec![],
+                headers: name_values(&entry.headers),
+                query_string: Query::<Vec<(String, String)>>::from_query(query)
+                    .map(|query| query.into_inner().into_iter().map(|(name, value)| HarNameValue { name, value }).collect())
+                    .unwrap_or_default(),
+                post_data: (!entry.body.is_empty()).then(|| HarPostData {
+                    mime_type: request_content_type.unwrap_or_default().to_string(),
+                    text: entry.body.clone(),
+                }),
+                headers_size: -1,
+                body_size: entry.body.len() as i64,
+            },
+            response: HarResponse {
+                status: entry.response.status,
+                status_text: StatusCode::from_u16(entry.response.status).ok().and_then(|status| status.canonical_reason()).unwrap_or_default().to_string(),
+                http_version: HTTP_VERSION.to_string(),
+                cookies: vec![],
+                headers: name_values(&entry.response.headers),
+                content: HarContent {
+                    size: entry.response.body.as_ref().map_or(-1, |body| body.len() as i64),
+                    mime_type: response_content_type.to_string(),
+                    text: entry.response.body.clone(),
+                },
+                redirect_url: header_value(&entry.response.headers, "location").unwrap_or_default().to_string(),
+                headers_size: -1,
+                body_size: entry.response.body.as_ref().map_or(-1, |body| body.len() as i64),
+            },
+            cache: HarCache {},
+            timings: HarTimings {
+                send: 0,
+                wait: entry.response.latency,
+                receive: 0,
+            },
+            server_id: entry.server_id.clone(),
+            endpoint_id: entry.endpoint_id.clone(),
+        }
+    }
+}
+
+/**
+ * A request in the HAR document.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarRequest {
+    // The HTTP method.
+    pub method: String,
+    // The absolute URL.
+    pub url: String,
+    // The HTTP version.
+    pub http_version: String,
+    // The cookies. Cookies are only available in the headers.
+    pub cookies: Vec<HarNameValue>,
+    // The request headers.
+    pub headers: Vec<HarNameValue>,
+    // The query parameters.
+    pub query_string: Vec<HarNameValue>,
+    // The request body. Not set if the request has no body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<HarPostData>,
+    // The size of the headers. Always -1 since it is not recorded.
+    pub headers_size: i64,
+    // The size of the request body.
+    pub body_size: i64,
+}
+
+/**
+ * A response in the HAR document.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarResponse {
+    // The status code.
+    pub status: u16,
+    // The reason phrase of the status code.
+    pub status_text: String,
+    // The HTTP version.
+    pub http_version: String,
+    // The cookies. Cookies are only available in the headers.
+    pub cookies: Vec<HarNameValue>,
+    // The response headers.
+    pub headers: Vec<HarNameValue>,
+    // The response body.
+    pub content: HarContent,
+    // The location header of redirects.
+    #[serde(rename = "redirectURL")]
+    pub redirect_url: String,
+    // The size of the headers. Always -1 since it is not recorded.
+    pub headers_size: i64,
+    // The size of the response body, or -1 for streamed responses.
+    pub body_size: i64,
+}
+
+/**
+ * A name and value pair used for headers, cookies and query parameters.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarNameValue {
+    // The name.
+    pub name: String,
+    // The value.
+    pub value: String,
+}
+
+/**
+ * The body of a request.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarPostData {
+    // The content type.
+    pub mime_type: String,
+    // The body.
+    pub text: String,
+}
+
+/**
+ * The body of a response.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarContent {
+    // The size of the body, or -1 for streamed responses.
+    pub size: i64,
+    // The content type.
+    pub mime_type: String,
+    // The body. Not set for streamed responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/**
+ * The cache state of a request.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarCache {}
+
+/**
+ * The time spent in each phase of a request in milliseconds.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarTimings {
+    // The time sending the request. Always 0 since the request is timed from it was received.
+    pub send: u64,
+    // The time until the response was ready.
+    pub wait: u64,
+    // The time receiving the response. Always 0 since the time of writing the response is not recorded.
+    pub receive: u64,
+}
+
+/**
+ * Get the first value of the header.
+ *
+ * @param headers The header names and values.
+ * @param name The lower case header name.
+ *
+ * @return The header value, or None if the header is missing.
+ */
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+/**
+ * Convert the header names and values to HAR name and value pairs.
+ *
+ * @param headers The header names and values.
+ *
+ * @return The name and value pairs.
+ */
+fn name_values(headers: &[(String, String)]) -> Vec<HarNameValue> {
+    headers.iter().map(|(name, value)| HarNameValue { name: name.clone(), value: value.clone() }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::journal::JournalResponse;
+
+    use super::*;
+
+    /**
+     * Test converting a journal entry to a HAR entry.
+     */
+    #[test]
+    fn test_har() {
+        let entry = JournalEntry {
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            server_id: "server".to_string(),
+            endpoint_id: "orders".to_string(),
+            method: "POST".to_string(),
+            scheme: "https".to_string(),
+            url: "/orders?id=1&name=a%20b".to_string(),
+            headers: vec![("host".to_string(), "localhost:8443".to_string()), ("content-type".to_string(), "application/json".to_string())],
+            body: "{}".to_string(),
+            response: JournalResponse { status: 201, headers: vec![], body: None, latency: 12 },
+        };
+        let har = Har::new(&[entry]);
+        assert_eq!(har.log.version, "1.2");
+        let entry = &har.log.entries[0];
+        assert_eq!(entry.request.url, "https://localhost:8443/orders?id=1&name=a%20b");
+        assert_eq!(entry.request.query_string[1], HarNameValue { name: "name".to_string(), value: "a b".to_string() });
+        assert_eq!(entry.request.post_data.as_ref().unwrap().mime_type, "application/json");
+        assert_eq!(entry.response.status_text, "Created");
+        assert_eq!(entry.response.content.size, -1);
+        assert_eq!(entry.timings.wait, 12);
+        let json = serde_json::to_value(&har).unwrap();
+        assert_eq!(json["log"]["entries"][0]["_endpointId"], "orders");
+        assert_eq!(json["log"]["entries"][0]["cache"], serde_json::json!({}));
+        assert!(json["log"]["entries"][0]["response"]["content"].get("text").is_none());
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * The endpoint id recorded for requests that did not match an endpoint.
+ */
+pub const UNMATCHED: &str = "unmatched";
+
+/**
+ * A request received by a server and the response that was sent.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    // The time the request was received in RFC 3339 format.
+    pub timestamp: String,
+    // The ID of the server that received the request.
+    pub server_id: String,
+    // The ID of the matched endpoint, or unmatched.
+    pub endpoint_id: String,
+    // The HTTP method.
+    pub method: String,
+    // The scheme the request was received with.
+    pub scheme: String,
+    // The path and query of the request.
+    pub url: String,
+    // The request headers in the order they were received.
+    pub headers: Vec<(String, String)>,
+    // The request body. Invalid UTF-8 is replaced.
+    pub body: String,
+    // The response that was sent.
+    pub response: JournalResponse,
+}
+
+/**
+ * The response sent for a request in the journal.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalResponse {
+    // The status code.
+    pub status: u16,
+    // The response headers.
+    pub headers: Vec<(String, String)>,
+    // The response body. Not set for streamed responses. Invalid UTF-8 is replaced.
+    pub body: Option<String>,
+    // The time in milliseconds from the request was received until the response headers were ready.
+    pub latency: u64,
+}
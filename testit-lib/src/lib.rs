@@ -1,2 +1,5 @@
 pub mod config;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod har;
+pub mod journal;
+pub mod verify;
\ No newline at end of file
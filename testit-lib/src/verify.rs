@@ -0,0 +1,197 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ApplicationError, journal::JournalEntry};
+
+/**
+ * The expected calls to an endpoint. Requests are selected by the server, endpoint, method and url and
+ * counted if the body matches.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationRequest {
+    // Only count requests received by the server with this ID.
+    pub server_id: Option<String>,
+    // Only count requests matched by the endpoint with this ID.
+    pub endpoint_id: Option<String>,
+    // Only count requests with this method.
+    pub method: Option<String>,
+    // Only count requests where the path and query matches this regular expression.
+    pub url: Option<String>,
+    // The regular expression the body must match.
+    pub body: Option<String>,
+    // The exact number of expected calls. If not set the endpoint must be called at least once.
+    pub count: Option<usize>,
+}
+
+impl VerificationRequest {
+    /**
+     * Create a new verification request for an endpoint.
+     *
+     * @param endpoint_id The ID of the endpoint.
+     *
+     * @return The verification request.
+     */
+    pub fn new(endpoint_id: String) -> Self {
+        VerificationRequest {
+            endpoint_id: Some(endpoint_id),
+            ..Default::default()
+        }
+    }
+
+    /**
+     * Set the regular expression the body must match.
+     *
+     * @param body The regular expression.
+     *
+     * @return The verification request.
+     */
+    pub fn with_body(mut self, body: String) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /**
+     * Set the exact number of expected calls.
+     *
+     * @param count The number of calls.
+     *
+     * @return The verification request.
+     */
+    pub fn with_count(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+}
+
+/**
+ * The result of a verification.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationResult {
+    // True if the number of matching requests is as expected.
+    pub passed: bool,
+    // The exact number of expected calls, or None if at least one call was expected.
+    pub expected_count: Option<usize>,
+    // The number of matching requests.
+    pub actual_count: usize,
+    // The selected requests where the body did not match.
+    pub mismatches: Vec<JournalEntry>,
+}
+
+/**
+ * Verify the calls received by a running daemon. The verification request is posted to `/__admin/verify` on
+ * the admin server, which verifies the calls in its journal.
+ *
+ * @param admin_url The URL of the admin server, for example http://localhost:9090.
+ * @param verification The expected calls.
+ *
+ * @return The verification result.
+ *
+ * # Errors
+ * @return An error if the admin server could not be reached or rejected the verification request.
+ */
+pub async fn verify_remote(admin_url: &str, verification: &VerificationRequest) -> Result<VerificationResult, ApplicationError> {
+    let body = serde_json::to_vec(verification).map_err(|err| ApplicationError::RequestError(err.to_string()))?;
+    let response = reqwest::Client::new()
+        .post(format!("{}/__admin/verify", admin_url.trim_end_matches('/')))
+        .header("content-type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| ApplicationError::RequestError(err.to_string()))?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(|err| ApplicationError::RequestError(err.to_string()))?;
+    if !status.is_success() {
+        return Err(ApplicationError::RequestError(format!("Admin server returned {}: {}", status, String::from_utf8_lossy(&body))));
+    }
+    serde_json::from_slice(&body).map_err(|err| ApplicationError::RequestError(err.to_string()))
+}
+
+/**
+ * Verify the calls in the journal entries. Use verify_remote to verify the calls received by a running daemon.
+ *
+ * @param entries The journal entries.
+ * @param verification The expected calls.
+ *
+ * @return The verification result.
+ *
+ * # Errors
+ * @return An error if the url or body is not a valid regular expression.
+ */
+pub fn verify(entries: &[JournalEntry], verification: &VerificationRequest) -> Result<VerificationResult, ApplicationError> {
+    let url = compile(verification.url.as_deref())?;
+    let body = compile(verification.body.as_deref())?;
+    let (matching, mismatches): (Vec<&JournalEntry>, Vec<&JournalEntry>) = entries
+        .iter()
+        .filter(|entry| verification.server_id.as_ref().is_none_or(|server_id| &entry.server_id == server_id))
+        .filter(|entry| verification.endpoint_id.as_ref().is_none_or(|endpoint_id| &entry.endpoint_id == endpoint_id))
+        .filter(|entry| verification.method.as_ref().is_none_or(|method| entry.method.eq_ignore_ascii_case(method)))
+        .filter(|entry| url.as_ref().is_none_or(|url| url.is_match(&entry.url)))
+        .partition(|entry| body.as_ref().is_none_or(|body| body.is_match(&entry.body)));
+    let actual_count = matching.len();
+    let passed = match verification.count {
+        Some(count) => actual_count == count,
+        None => actual_count > 0,
+    };
+    Ok(VerificationResult {
+        passed,
+        expected_count: verification.count,
+        actual_count,
+        mismatches: mismatches.into_iter().cloned().collect(),
+    })
+}
+
+/**
+ * Compile the optional regular expression.
+ *
+ * @param pattern The regular expression.
+ *
+ * @return The compiled regular expression, or None if there is no pattern.
+ *
+ * # Errors
+ * @return An error if the pattern is not a valid regular expression.
+ */
+fn compile(pattern: Option<&str>) -> Result<Option<Regex>, ApplicationError> {
+    pattern
+        .map(|pattern| Regex::new(pattern).map_err(|err| ApplicationError::ConfigurationError(format!("Invalid verification pattern {}: {}", pattern, err))))
+        .transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::journal::JournalResponse;
+
+    use super::*;
+
+    /**
+     * Test verifying the number of calls with a matching body.
+     */
+    #[test]
+    fn test_verify() {
+        let entries: Vec<JournalEntry> = [("orders", "{\"id\": 1}"), ("orders", "{\"id\": 2}"), ("other", "{\"id\": 1}")]
+            .into_iter()
+            .map(|(endpoint_id, body)| JournalEntry {
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                server_id: "server".to_string(),
+                endpoint_id: endpoint_id.to_string(),
+                method: "POST".to_string(),
+                scheme: "http".to_string(),
+                url: "/orders".to_string(),
+                headers: vec![],
+                body: body.to_string(),
+                response: JournalResponse { status: 200, headers: vec![], body: None, latency: 0 },
+            })
+            .collect();
+        let result = verify(&entries, &VerificationRequest::new("orders".to_string()).with_body("\"id\": 1".to_string()).with_count(1)).unwrap();
+        assert!(result.passed);
+        assert_eq!(result.actual_count, 1);
+        assert_eq!(result.mismatches, vec![entries[1].clone()]);
+        let result = verify(&entries, &VerificationRequest::new("orders".to_string()).with_count(1)).unwrap();
+        assert!(!result.passed);
+        assert_eq!(result.actual_count, 2);
+        assert!(!verify(&entries, &VerificationRequest::new("missing".to_string())).unwrap().passed);
+        assert!(verify(&entries, &VerificationRequest::new("orders".to_string()).with_body("(".to_string())).is_err());
+    }
+}